@@ -1,3 +1,4 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
 
@@ -28,15 +29,32 @@ pub struct PlayResult {
     pub session_id: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct PublishParam {
+    pub api: String,
+    #[serde(rename = "clientip")]
+    pub client_ip: Option<IpAddr>,
+    pub sdp: String,
+    #[serde(rename = "streamurl")]
+    pub stream_url: String,
+    pub tid: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishResult {
+    pub code: i32,
+    pub server: Option<String>,
+    pub sdp: String,
+    #[serde(rename = "sessionid")]
+    pub session_id: String,
+}
+
 impl ApiClient {
     pub fn new(host: &str, port: u16) -> Self {
         Self {
             host: host.to_string(),
             port,
-            http: reqwest::ClientBuilder::new()
-                .danger_accept_invalid_certs(true)
-                .build()
-                .expect("Reqwest client error"),
+            http: new_http_client(),
         }
     }
 
@@ -44,8 +62,28 @@ impl ApiClient {
         format!("https://{}:{}/rtc/v1/play/", self.host, self.port)
     }
 
+    pub fn publish_api_url(&self) -> String {
+        format!("https://{}:{}/rtc/v1/publish/", self.host, self.port)
+    }
+
     pub async fn play(&self, param: &PlayParam) -> anyhow::Result<PlayResult> {
-        let resp = self.http.post(self.api_url()).json(param).send().await?;
+        let result: PlayResult = self.post(self.api_url(), param).await?;
+        anyhow::ensure!(result.code == 0, "Play failed with code {}", result.code);
+        Ok(result)
+    }
+
+    pub async fn publish(&self, param: &PublishParam) -> anyhow::Result<PublishResult> {
+        let result: PublishResult = self.post(self.publish_api_url(), param).await?;
+        anyhow::ensure!(result.code == 0, "Publish failed with code {}", result.code);
+        Ok(result)
+    }
+
+    async fn post<P: Serialize, R: DeserializeOwned>(
+        &self,
+        url: String,
+        param: &P,
+    ) -> anyhow::Result<R> {
+        let resp = self.http.post(url).json(param).send().await?;
 
         if resp.status().is_success() {
             Ok(resp.json().await?)
@@ -58,3 +96,85 @@ impl ApiClient {
         }
     }
 }
+
+/// WHIP (WebRTC-HTTP ingestion protocol) 客户端
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-wish-whip
+#[derive(Debug, Clone)]
+pub struct WhipClient {
+    endpoint: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+#[derive(Debug)]
+pub struct WhipSession {
+    /// 应答 SDP
+    pub sdp: String,
+
+    /// WHIP 资源地址，用于结束推流
+    pub location: Option<String>,
+}
+
+impl WhipClient {
+    pub fn new(endpoint: &str, token: Option<String>) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            token,
+            http: new_http_client(),
+        }
+    }
+
+    pub async fn publish(&self, offer: String) -> anyhow::Result<WhipSession> {
+        let mut request = self
+            .http
+            .post(&self.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/sdp")
+            .body(offer);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let resp = request.send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Error response: {} {}",
+                resp.status(),
+                resp.text().await?
+            ));
+        }
+
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .map(|location| match resp.url().join(location) {
+                Ok(url) => url.to_string(),
+                Err(_) => location.to_string(),
+            });
+
+        Ok(WhipSession {
+            sdp: resp.text().await?,
+            location,
+        })
+    }
+
+    pub async fn delete(&self, session: &WhipSession) -> anyhow::Result<()> {
+        if let Some(location) = &session.location {
+            let mut request = self.http.delete(location);
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            request.send().await?.error_for_status()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn new_http_client() -> reqwest::Client {
+    reqwest::ClientBuilder::new()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .expect("Reqwest client error")
+}
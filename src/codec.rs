@@ -1,328 +1,885 @@
-use bytes::{BufMut, BytesMut};
-// use ffmpeg::filter::Graph;
-// use ffmpeg::{Filter, Frame, Rational};
-// use ffmpeg_sys_next::*;
-use openh264::encoder::{EncoderConfig, FrameType};
+use bytes::Bytes;
+use openh264::decoder::Decoder;
+use openh264::encoder::{Encoder, EncoderConfig, FrameType};
 use openh264::formats::YUVSource;
 // use photon_rs::native::save_image;
 // use photon_rs::PhotonImage;
-use crate::h264::H264Data;
-// use serde::{Deserialize, Serialize};
+use crate::compositor::{Compose, FrameSlot, OutputClock};
+use crate::control::JobUpdates;
+use crate::h264::{
+    nal_unit_type, split_annexb, AVCDecoderConfigurationRecord, H264Data, SequenceParameterSet,
+    NAL_UNIT_TYPE_IDR, NAL_UNIT_TYPE_SPS,
+};
+use crate::param::{Canvas, EncoderSettings, RateControl, Rotation};
+use crate::rtmp::VideoPacket;
+use crate::yuv::YuvFrame;
+use openh264_sys2::{
+    SEncParamExt, ENCODER_OPTION_SVC_ENCODE_PARAM_EXT, RC_BITRATE_MODE, RC_BUFFERBASED_MODE,
+    RC_OFF_MODE, RC_QUALITY_MODE, RC_TIMESTAMP_MODE, UNSPECIFIED_BIT_RATE,
+};
+use std::os::raw::c_int;
+use std::ptr::addr_of_mut;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, UnboundedSender};
+use tokio::sync::Notify;
 
-// const INPUT_FORMAT: AVPixelFormat = AVPixelFormat::AV_PIX_FMT_YUV420P;
+/// 解码源 H264，按显示顺序将帧写入 slot，由 [`encode`] 按输出时钟合成编码
+///
+/// 解码出错或 SPS 损坏时重置解码器并向上游请求关键帧，恢复前 slot 保留最后一帧正常画面；
+/// 源 SPS 不支持时重置也无法恢复，直接返回错误
+pub fn decode(
+    id: String,
+    mut receiver: Receiver<H264Data>,
+    slot: FrameSlot,
+    keyframe_request: Arc<Notify>,
+    orientation: Arc<AtomicU8>,
+) -> anyhow::Result<()> {
+    let mut source = SourceDecoder::new(id, slot, orientation)?;
+
+    // padding: 15360
+    // 153600
+    // 138240
+    while let Some(packet) = receiver.blocking_recv() {
+        let result = match packet {
+            // 损坏的 SPS 按解码错误处理，解析成功但不支持的 SPS 无法通过重置恢复
+            H264Data::Configuration { raw, record } => match record.sps() {
+                Ok(sps) => {
+                    source.apply_sps(&sps)?;
+                    source.configure(raw)
+                }
+                Err(e) => Err(e),
+            },
+            H264Data::Data { data, .. } => {
+                if source.waiting_keyframe && !is_recovery_point(&data) {
+                    continue;
+                }
+                // 切换 simulcast 层或更换摄像头时带内 SPS 会携带新的分辨率
+                match in_band_sps(&data) {
+                    Ok(sps) => {
+                        for sps in &sps {
+                            source.apply_sps(sps)?;
+                        }
+                        source.decode_packet(&data)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        if let Err(e) = result {
+            source.errors += 1;
+            log::warn!(
+                "Source {} decode error({} total): {}",
+                source.id,
+                source.errors,
+                e
+            );
+            keyframe_request.notify_one();
+            source.reset()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 解析 CVO 字节（3GPP TS 26.114），低两位为需顺时针旋转的角度，第 3 位为水平翻转
+fn orientation(cvo: u8) -> (Rotation, bool) {
+    (Rotation::from_quarters(cvo & 0x03), cvo & 0x04 != 0)
+}
+
+/// 解析数据中的带内 SPS
+fn in_band_sps(packet: &[u8]) -> anyhow::Result<Vec<SequenceParameterSet>> {
+    split_annexb(packet)
+        .into_iter()
+        .filter(|nal| nal_unit_type(nal) == NAL_UNIT_TYPE_SPS)
+        .map(SequenceParameterSet::parse)
+        .collect()
+}
+
+/// 解码器重置后需从 SPS 或 IDR 开始解码
+fn is_recovery_point(data: &[u8]) -> bool {
+    split_annexb(data)
+        .iter()
+        .any(|nal| matches!(nal_unit_type(nal), NAL_UNIT_TYPE_SPS | NAL_UNIT_TYPE_IDR))
+}
 
-enum State {
-    Created,
-    HeaderReceived(openh264::encoder::Encoder),
+/// 单路源的解码状态
+struct SourceDecoder {
+    id: String,
+    decoder: Decoder,
+    slot: FrameSlot,
+    /// 源的视频方向（CVO 字节），输出前按此旋转
+    orientation: Arc<AtomicU8>,
+    source_size: Option<(usize, usize)>,
+    /// 最近一次收到的 SPS/PPS，重置解码器后重新送入
+    parameter_sets: Option<Bytes>,
+    waiting_keyframe: bool,
+    errors: u64,
+    count: u64,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        Self::Created
+impl SourceDecoder {
+    fn new(id: String, slot: FrameSlot, orientation: Arc<AtomicU8>) -> anyhow::Result<Self> {
+        Ok(Self {
+            id,
+            decoder: Decoder::new()?,
+            slot,
+            orientation,
+            source_size: None,
+            parameter_sets: None,
+            waiting_keyframe: false,
+            errors: 0,
+            count: 0,
+        })
+    }
+
+    fn configure(&mut self, raw: Bytes) -> anyhow::Result<()> {
+        self.parameter_sets = Some(raw.clone());
+
+        let yuv = self.decoder.decode(raw.as_ref())?;
+        log::info!(
+            "{}) width: {}, height: {}, strides: {:?}, y: {}, u: {}, v: {}, yuv: {}",
+            self.count,
+            yuv.width(),
+            yuv.height(),
+            yuv.strides_yuv(),
+            yuv.y().len(),
+            yuv.u().len(),
+            yuv.v().len(),
+            yuv.y().len() + yuv.u().len() + yuv.v().len()
+        );
+        self.count += 1;
+        Ok(())
+    }
+
+    /// 检查源 SPS 能否解码
+    fn apply_sps(&mut self, sps: &SequenceParameterSet) -> anyhow::Result<()> {
+        log::info!(
+            "Source SPS: {}x{}, {} profile, level {}, frame rate: {:?}",
+            sps.width(),
+            sps.height(),
+            sps.profile_name(),
+            sps.level(),
+            sps.frame_rate()
+        );
+        sps.ensure_decodable()
+    }
+
+    fn decode_packet(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        log::info!("Depacketized H264 data({}B): {:02x?}", packet.len(), packet);
+        if self.waiting_keyframe {
+            log::info!("Source {} recovered from decode errors", self.id);
+            self.waiting_keyframe = false;
+        }
+
+        let yuv = self.decoder.decode(packet)?;
+        log::info!(
+            "{}) width: {}, height: {}, strides: {:?}, y: {}, u: {}, v: {}, yuv: {}",
+            self.count,
+            yuv.width(),
+            yuv.height(),
+            yuv.strides_yuv(),
+            yuv.y().len(),
+            yuv.u().len(),
+            yuv.v().len(),
+            yuv.y().len() + yuv.u().len() + yuv.v().len()
+        );
+        self.count += 1;
+
+        if yuv.width() == 0 {
+            return Ok(());
+        }
+
+        let frame = YuvFrame::copy_from(&yuv);
+        if self.source_size != Some(frame.dimensions()) {
+            if let Some((width, height)) = self.source_size {
+                let (new_width, new_height) = frame.dimensions();
+                log::info!(
+                    "Source resolution changed: {}x{} -> {}x{}",
+                    width,
+                    height,
+                    new_width,
+                    new_height
+                );
+            }
+            self.source_size = Some(frame.dimensions());
+        }
+
+        // openh264 已按显示顺序输出含 B 帧的码流，无需再按 pts 重排
+        self.output(frame);
+        Ok(())
+    }
+
+    /// 按视频方向旋转后写入 slot
+    fn output(&mut self, frame: YuvFrame) {
+        let (rotation, flip) = orientation(self.orientation.load(Ordering::Relaxed));
+        if rotation == Rotation::None && !flip {
+            self.slot.put(frame);
+            return;
+        }
+        let mut frame = frame.rotated(rotation);
+        frame.flip(flip, false);
+        self.slot.put(frame);
+    }
+
+    /// 重建解码器并送入已知的 SPS/PPS，等待下一个关键帧
+    fn reset(&mut self) -> anyhow::Result<()> {
+        self.decoder = Decoder::new()?;
+        if let Some(parameter_sets) = &self.parameter_sets {
+            if let Err(e) = self.decoder.decode(parameter_sets) {
+                log::warn!("Source {} failed to restore SPS/PPS: {}", self.id, e);
+            }
+        }
+        self.waiting_keyframe = true;
+        Ok(())
     }
 }
 
-pub fn decode(
-    mut receiver: Receiver<H264Data>,
-    h264_sender: UnboundedSender<H264Data>,
+/// 按画布帧率合成并编码输出，RTMP 推流端关闭后退出
+pub fn encode(
+    mut compositor: impl Compose,
+    mut updates: JobUpdates,
+    h264_sender: UnboundedSender<VideoPacket>,
+    mut rtc_sender: Option<UnboundedSender<H264Data>>,
 ) -> anyhow::Result<()> {
-    let mut decoder = openh264::decoder::Decoder::new()?;
+    let canvas = compositor.canvas().clone();
+    log::info!(
+        "Output canvas: {}x{}@{}fps",
+        canvas.width,
+        canvas.height,
+        canvas.fps
+    );
+    compositor.set_layout(&updates.layout.borrow_and_update());
+    let mut encoder = new_encoder(&canvas, &updates.encoder.borrow_and_update())?;
+    let mut clock = OutputClock::new(canvas.fps);
+    let mut sequence_header: Option<AVCDecoderConfigurationRecord> = None;
 
-    let mut state = State::default();
+    loop {
+        let timestamp = clock.tick();
+        // 编码参数变化后重建编码器，从关键帧重新开始
+        if updates.encoder.has_changed().unwrap_or(false) {
+            encoder = new_encoder(&canvas, &updates.encoder.borrow_and_update())?;
+        }
+        if updates.layout.has_changed().unwrap_or(false) {
+            compositor.set_layout(&updates.layout.borrow_and_update());
+        }
+        let encoded_frame = encoder.encode(compositor.compose(timestamp))?;
+        let key_frame = match encoded_frame.frame_type() {
+            FrameType::IDR | FrameType::I => true,
+            FrameType::P => false,
+            _ => continue,
+        };
+        let data = encoded_frame.to_vec();
+        send_raw(&mut rtc_sender, timestamp, data.clone());
 
-    // let config = FilterConfig::default();
-    // let mut graph = build_filter_chain(&config)?;
+        if encoded_frame.frame_type() == FrameType::IDR {
+            match AVCDecoderConfigurationRecord::from_annexb(&data) {
+                Some(record) => {
+                    if !send_sequence_header(&h264_sender, &mut sequence_header, record) {
+                        break;
+                    }
+                }
+                None => log::warn!("IDR frame without SPS/PPS from encoder"),
+            }
+        }
 
-    // let mut frame = build_frame(&config);
+        if sequence_header.is_none() {
+            log::warn!("Drop h264 frame before sequence header");
+            continue;
+        }
 
-    const CODEC_ID: u8 = 7;
+        log::info!(
+            "h264 frame type: {:?}, key frame: {}, {} bytes(hex): {:02x?}",
+            encoded_frame.frame_type(),
+            key_frame,
+            data.len(),
+            data
+        );
+        if h264_sender
+            .send(VideoPacket::Avc(H264Data::data(
+                timestamp,
+                timestamp,
+                data.into(),
+            )))
+            .is_err()
+        {
+            log::error!("Rtmp client closed while sending h264 data");
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// 按画布及编码参数创建编码器，EncoderConfig 未提供的参数通过底层接口设置
+fn new_encoder(canvas: &Canvas, settings: &EncoderSettings) -> anyhow::Result<Encoder> {
+    log::info!("Encoder settings: {:?}", settings);
+    let config = EncoderConfig::new(canvas.width, canvas.height).set_bitrate_bps(settings.bitrate);
+    let mut encoder = Encoder::with_config(config)?;
+
+    let rc_mode = match settings.rate_control {
+        RateControl::Quality => RC_QUALITY_MODE,
+        RateControl::Bitrate => RC_BITRATE_MODE,
+        RateControl::Buffer => RC_BUFFERBASED_MODE,
+        RateControl::Timestamp => RC_TIMESTAMP_MODE,
+        RateControl::Off => RC_OFF_MODE,
+    };
+    let max_bitrate = settings
+        .max_bitrate
+        .map(|bps| bps as c_int)
+        .unwrap_or(UNSPECIFIED_BIT_RATE as c_int);
+
+    // 只修改画面尺寸以外的参数，不影响 Encoder 内部对帧尺寸的检查
+    unsafe {
+        let raw_api = encoder.raw_api();
+        let mut params = SEncParamExt::default();
+        let result = raw_api.get_option(
+            ENCODER_OPTION_SVC_ENCODE_PARAM_EXT,
+            addr_of_mut!(params).cast(),
+        );
+        anyhow::ensure!(result == 0, "Failed to get encoder params: {}", result);
+
+        params.iRCMode = rc_mode;
+        params.iTargetBitrate = settings.bitrate as c_int;
+        params.iMaxBitrate = max_bitrate;
+        params.fMaxFrameRate = canvas.fps as f32;
+        params.uiIntraPeriod = settings.keyframe_interval;
+        params.bEnableFrameSkip = settings.skip_frame;
+        params.iMultipleThreadIdc = settings.threads;
+        let layer = &mut params.sSpatialLayers[0];
+        layer.fFrameRate = canvas.fps as f32;
+        layer.iSpatialBitrate = settings.bitrate as c_int;
+        layer.iMaxSpatialBitrate = max_bitrate;
+
+        let result = raw_api.set_option(
+            ENCODER_OPTION_SVC_ENCODE_PARAM_EXT,
+            addr_of_mut!(params).cast(),
+        );
+        anyhow::ensure!(result == 0, "Failed to set encoder params: {}", result);
+    }
+
+    Ok(encoder)
+}
+
+/// 透传模式：不解码也不重新编码，将源 H264 按访问单元直接转封装
+pub fn remux(
+    mut receiver: Receiver<H264Data>,
+    h264_sender: UnboundedSender<VideoPacket>,
+    mut rtc_sender: Option<UnboundedSender<H264Data>>,
+) -> anyhow::Result<()> {
+    let mut sequence_header: Option<AVCDecoderConfigurationRecord> = None;
+    // RTP 解包输出单个 NAL，按 marker 位合并为访问单元
+    let mut access_unit: Option<(u32, u32, Vec<u8>)> = None;
 
-    // padding: 15360
-    // 153600
-    // 138240
-    let mut count = 0;
     while let Some(packet) = receiver.blocking_recv() {
         match packet {
-            H264Data::Configuration { raw, record } => {
-                let yuv = decoder.decode(raw.as_ref())?;
+            H264Data::Configuration { record, .. } => {
+                let sps = match record.sps() {
+                    Ok(sps) => sps,
+                    Err(e) => {
+                        log::error!("Skip h264 configuration with invalid SPS: {:?}", e);
+                        continue;
+                    }
+                };
                 log::info!(
-                    "{count}) width: {}, height: {}, strides: {:?}, y: {}, u: {}, v: {}, yuv: {}",
-                    yuv.width(),
-                    yuv.height(),
-                    yuv.strides_yuv(),
-                    yuv.y().len(),
-                    yuv.u().len(),
-                    yuv.v().len(),
-                    yuv.y().len() + yuv.u().len() + yuv.v().len()
+                    "Source SPS: {}x{}, {} profile, level {}, frame rate: {:?}",
+                    sps.width(),
+                    sps.height(),
+                    sps.profile_name(),
+                    sps.level(),
+                    sps.frame_rate()
                 );
-                count += 1;
-                let mut buffer = BytesMut::new();
-                buffer.put_u8(0x17);
-                buffer.put_u32(0);
-                record.write_to(&mut buffer);
-                let buffer = buffer.freeze();
-                if h264_sender.send(H264Data::data(0, buffer)).is_err() {
-                    log::error!("Rtmp client closed while sending h264 configuration");
-                    break;
+                if !send_sequence_header(&h264_sender, &mut sequence_header, *record) {
+                    return Ok(());
                 }
             }
             H264Data::Data {
-                data: packet,
-                timestamp,
+                dts,
+                pts,
+                data,
+                marker,
             } => {
-                log::info!(
-                    "Depacketized H264 data({}B): {:02x?}",
-                    packet.len(),
-                    packet.as_ref()
-                );
-                let yuv = decoder.decode(packet.as_ref())?;
-                log::info!(
-                    "{count}) width: {}, height: {}, strides: {:?}, y: {}, u: {}, v: {}, yuv: {}",
-                    yuv.width(),
-                    yuv.height(),
-                    yuv.strides_yuv(),
-                    yuv.y().len(),
-                    yuv.u().len(),
-                    yuv.v().len(),
-                    yuv.y().len() + yuv.u().len() + yuv.v().len()
-                );
-                count += 1;
+                // 带 marker 的包丢失时，时间戳变化也结束上一个访问单元
+                if matches!(&access_unit, Some((unit_dts, _, _)) if *unit_dts != dts) {
+                    let (dts, pts, buffer) = access_unit.take().unwrap();
+                    if !send_access_unit(
+                        &h264_sender,
+                        &mut rtc_sender,
+                        &mut sequence_header,
+                        dts,
+                        pts,
+                        buffer,
+                    ) {
+                        return Ok(());
+                    }
+                }
+                access_unit
+                    .get_or_insert_with(|| (dts, pts, Vec::new()))
+                    .2
+                    .extend_from_slice(&data);
+                if marker {
+                    let (dts, pts, buffer) = access_unit.take().unwrap();
+                    if !send_access_unit(
+                        &h264_sender,
+                        &mut rtc_sender,
+                        &mut sequence_header,
+                        dts,
+                        pts,
+                        buffer,
+                    ) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
 
-                if yuv.width() == 0 {
-                    continue;
+    // 源结束时发送最后一个访问单元
+    if let Some((dts, pts, buffer)) = access_unit {
+        send_access_unit(
+            &h264_sender,
+            &mut rtc_sender,
+            &mut sequence_header,
+            dts,
+            pts,
+            buffer,
+        );
+    }
+    Ok(())
+}
+
+/// 带内 SPS/PPS 变化时先发送新的 sequence header，尚无 sequence header 时丢弃，返回 false 表示 RTMP 推流端已关闭
+fn send_access_unit(
+    h264_sender: &UnboundedSender<VideoPacket>,
+    rtc_sender: &mut Option<UnboundedSender<H264Data>>,
+    sequence_header: &mut Option<AVCDecoderConfigurationRecord>,
+    dts: u32,
+    pts: u32,
+    data: Vec<u8>,
+) -> bool {
+    if let Some(record) = AVCDecoderConfigurationRecord::from_annexb(&data) {
+        if !send_sequence_header(h264_sender, sequence_header, record) {
+            return false;
+        }
+    }
+    send_raw(rtc_sender, pts, data.clone());
+    // 没有 sequence header 时 RTMP 播放端无法解码
+    if sequence_header.is_none() {
+        log::warn!("Drop h264 frame before sequence header");
+        return true;
+    }
+    if h264_sender
+        .send(VideoPacket::Avc(H264Data::data(dts, pts, data.into())))
+        .is_err()
+    {
+        log::error!("Rtmp client closed while sending h264 data");
+        return false;
+    }
+    true
+}
+
+/// 与上一次不同时发送 sequence header，返回 false 表示 RTMP 推流端已关闭
+fn send_sequence_header(
+    h264_sender: &UnboundedSender<VideoPacket>,
+    sequence_header: &mut Option<AVCDecoderConfigurationRecord>,
+    record: AVCDecoderConfigurationRecord,
+) -> bool {
+    if sequence_header.as_ref() == Some(&record) {
+        return true;
+    }
+    log::info!("h264 sequence header: {:02x?}", record);
+    let configuration = H264Data::configuration(record.to_annexb().into(), record.clone());
+    if h264_sender.send(VideoPacket::Avc(configuration)).is_err() {
+        log::error!("Rtmp client closed");
+        return false;
+    }
+    *sequence_header = Some(record);
+    true
+}
+
+/// 将编码后的 Annex-B 码流发送到 WebRTC 推流端，推流端关闭后不再发送
+fn send_raw(sender: &mut Option<UnboundedSender<H264Data>>, timestamp: u32, data: Vec<u8>) {
+    if let Some(s) = sender {
+        if s.send(H264Data::data(timestamp, timestamp, data.into()))
+            .is_err()
+        {
+            log::warn!("WebRTC publisher closed, stop sending h264 frames");
+            *sender = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按位写入 RBSP，用于构造测试码流
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        used: u32,
+    }
+
+    impl BitWriter {
+        fn bit(&mut self, bit: bool) {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            if bit {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> self.used;
+            }
+            self.used = (self.used + 1) % 8;
+        }
+
+        fn bits(&mut self, value: u32, n: u32) {
+            for i in (0..n).rev() {
+                self.bit(value >> i & 1 == 1);
+            }
+        }
+
+        /// 无符号指数哥伦布码，值为 0 的 se(v) 编码相同
+        fn ue(&mut self, value: u32) {
+            let value = value + 1;
+            let n = 32 - value.leading_zeros();
+            self.bits(0, n - 1);
+            self.bits(value, n);
+        }
+
+        fn align(&mut self) {
+            while self.used != 0 {
+                self.bit(false);
+            }
+        }
+
+        /// 补齐 rbsp_trailing_bits 并加入防竞争字节，输出带起始码的 NAL
+        fn nal(mut self, header: u8) -> Vec<u8> {
+            self.bit(true);
+            self.align();
+            let mut nal = vec![0, 0, 0, 1, header];
+            let mut zeros = 0;
+            for byte in self.bytes {
+                if zeros >= 2 && byte <= 3 {
+                    nal.push(3);
+                    zeros = 0;
                 }
+                zeros = if byte == 0 { zeros + 1 } else { 0 };
+                nal.push(byte);
+            }
+            nal
+        }
+    }
 
-                let mut vec = Vec::with_capacity(1024);
-
-                if let State::Created = state {
-                    log::info!(
-                        "Got video info: width={}, height={}",
-                        yuv.width(),
-                        yuv.height()
-                    );
-                    let config = EncoderConfig::new(yuv.width() as u32, yuv.height() as u32);
-                    let mut encoder = openh264::encoder::Encoder::with_config(config)?;
-
-                    let encoded_frame = encoder.encode(&yuv)?;
-
-                    let frame_type = match encoded_frame.frame_type() {
-                        FrameType::IDR | FrameType::I => 1,
-                        FrameType::P => 2,
-                        _ => continue,
-                    };
-                    vec.put_u8(frame_type << 4 | CODEC_ID);
-                    vec.put_slice(&[1, 0, 0, 0]);
-
-                    if encoded_frame.frame_type() == FrameType::IDR {
-                        let layer = encoded_frame.layer(1).unwrap();
-
-                        for n in 0..layer.nal_count() {
-                            let nal = layer.nal_unit(n).unwrap();
-                            vec.put_slice(nal);
-                        }
-                    }
+    /// 16x16 Main profile CAVLC 码流的 SPS 及 PPS，frame_mbs_only 为 false 时为不支持的隔行扫描
+    fn parameter_sets(frame_mbs_only: bool) -> Vec<u8> {
+        let mut sps = BitWriter::default();
+        sps.bits(77, 8); // profile_idc
+        sps.bits(0, 8);
+        sps.bits(30, 8); // level_idc
+        sps.ue(0); // seq_parameter_set_id
+        sps.ue(0); // log2_max_frame_num_minus4
+        sps.ue(0); // pic_order_cnt_type
+        sps.ue(0); // log2_max_pic_order_cnt_lsb_minus4
+        sps.ue(2); // max_num_ref_frames
+        sps.bit(false); // gaps_in_frame_num_value_allowed_flag
+        sps.ue(0); // pic_width_in_mbs_minus1
+        sps.ue(0); // pic_height_in_map_units_minus1
+        sps.bit(frame_mbs_only); // frame_mbs_only_flag
+        if !frame_mbs_only {
+            sps.bit(false); // mb_adaptive_frame_field_flag
+        }
+        sps.bit(true); // direct_8x8_inference_flag
+        sps.bit(false); // frame_cropping_flag
+        sps.bit(false); // vui_parameters_present_flag
 
-                    log::info!("h264 sequence header({}B): {:02x?}", vec.len(), vec);
-                    if h264_sender
-                        .send(H264Data::data(timestamp, vec.into()))
-                        .is_err()
-                    {
-                        log::error!("Rtmp client closed");
-                        break;
-                    }
+        let mut pps = BitWriter::default();
+        pps.ue(0); // pic_parameter_set_id
+        pps.ue(0); // seq_parameter_set_id
+        pps.bit(false); // entropy_coding_mode_flag
+        pps.bit(false); // bottom_field_pic_order_in_frame_present_flag
+        pps.ue(0); // num_slice_groups_minus1
+        pps.ue(0); // num_ref_idx_l0_default_active_minus1
+        pps.ue(0); // num_ref_idx_l1_default_active_minus1
+        pps.bit(false); // weighted_pred_flag
+        pps.bits(0, 2); // weighted_bipred_idc
+        pps.ue(0); // pic_init_qp_minus26
+        pps.ue(0); // pic_init_qs_minus26
+        pps.ue(0); // chroma_qp_index_offset
+        pps.bit(false); // deblocking_filter_control_present_flag
+        pps.bit(false); // constrained_intra_pred_flag
+        pps.bit(false); // redundant_pic_cnt_present_flag
 
-                    state = State::HeaderReceived(encoder);
-                } else if let State::HeaderReceived(encoder) = &mut state {
-                    let encoded_frame = encoder.encode(&yuv)?;
-                    let frame_type = match encoded_frame.frame_type() {
-                        FrameType::IDR | FrameType::I => 1,
-                        FrameType::P => 2,
-                        _ => continue,
-                    };
-                    let codec_id = 7;
-                    let packet_type: u8 = 1;
-                    let composition_time: i32 = 0; //if frame_type == 1 { 1 } else { 0 };
-                    let end = ((packet_type as i32) << 24) | composition_time;
-                    vec.put_u8(frame_type << 4 | codec_id);
-                    vec.put_i32(end);
-                    encoded_frame.write_vec(&mut vec);
-                    log::info!(
-                        "h264 frame type: {:?}, {} bytes(hex): {:02x?}",
-                        encoded_frame.frame_type(),
-                        vec.len(),
-                        vec
-                    );
-                    if h264_sender
-                        .send(H264Data::data(timestamp, vec.into()))
-                        .is_err()
-                    {
-                        log::error!("Rtmp client closed while sending h264 data");
-                        break;
-                    }
+        let mut data = sps.nal(0x67);
+        data.extend(pps.nal(0x68));
+        data
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum SliceType {
+        I,
+        P,
+        B,
+    }
+
+    /// 单个 I_PCM 宏块的帧，亮度取 luma，按显示序号设置 POC
+    fn picture(slice_type: SliceType, frame_num: u32, display: u32, luma: u8) -> Vec<u8> {
+        let mut slice = BitWriter::default();
+        slice.ue(0); // first_mb_in_slice
+        slice.ue(match slice_type {
+            SliceType::P => 5,
+            SliceType::B => 6,
+            SliceType::I => 7,
+        });
+        slice.ue(0); // pic_parameter_set_id
+        slice.bits(frame_num, 4);
+        if slice_type == SliceType::I {
+            slice.ue(0); // idr_pic_id
+        }
+        slice.bits(display * 2, 4); // pic_order_cnt_lsb
+        if slice_type == SliceType::B {
+            slice.bit(true); // direct_spatial_mv_pred_flag
+        }
+        if slice_type != SliceType::I {
+            slice.bit(false); // num_ref_idx_active_override_flag
+            slice.bit(false); // ref_pic_list_modification_flag_l0
+        }
+        if slice_type == SliceType::B {
+            slice.bit(false); // ref_pic_list_modification_flag_l1
+        }
+        // dec_ref_pic_marking，B 帧不作参考
+        match slice_type {
+            SliceType::I => slice.bits(0, 2),
+            SliceType::P => slice.bit(false),
+            SliceType::B => {}
+        }
+        slice.ue(0); // slice_qp_delta
+
+        if slice_type != SliceType::I {
+            slice.ue(0); // mb_skip_run
+        }
+        slice.ue(match slice_type {
+            SliceType::I => 25,
+            SliceType::P => 30,
+            SliceType::B => 48,
+        }); // I_PCM
+        slice.align();
+        for _ in 0..256 {
+            slice.bits(luma as u32, 8);
+        }
+        for _ in 0..128 {
+            slice.bits(128, 8);
+        }
+
+        match slice_type {
+            SliceType::I => slice.nal(0x65),
+            SliceType::P => slice.nal(0x41),
+            SliceType::B => slice.nal(0x01),
+        }
+    }
+
+    #[test]
+    fn output_b_frames_in_display_order() {
+        let luma = |display: u32| (32 + display * 40) as u8;
+        let mut stream = vec![parameter_sets(true)];
+        stream[0].extend(picture(SliceType::I, 0, 0, luma(0)));
+        stream.push(picture(SliceType::P, 1, 2, luma(2)));
+        stream.push(picture(SliceType::B, 2, 1, luma(1)));
+        stream.push(picture(SliceType::P, 2, 4, luma(4)));
+        stream.push(picture(SliceType::B, 3, 3, luma(3)));
+
+        let slot = FrameSlot::default();
+        let mut source =
+            SourceDecoder::new("b-frames".to_string(), slot.clone(), Default::default()).unwrap();
+        let mut output = Vec::new();
+        for packet in &stream {
+            source.decode_packet(packet).unwrap();
+            if let Some((frame, _)) = slot.latest() {
+                if output.last() != Some(&frame.y()[0]) {
+                    output.push(frame.y()[0]);
                 }
+            }
+        }
+
+        // 解码器缓存最后的参考帧，已输出的帧应按显示顺序排列
+        assert!(output.len() >= 3, "{:?}", output);
+        let display: Vec<u8> = (0..5).map(luma).collect();
+        assert_eq!(output, display[..output.len()]);
+    }
 
-                // let pts = if start == 0 {
-                //     start = ffmpeg::util::time::current();
-                //     0
-                // } else {
-                //     ffmpeg::util::time::current() - start
-                // };
-                //
-                // frame.set_pts(Some(pts));
-                // fill_frame(&yuv, &mut frame);
-                // graph
-                //     .get("in")
-                //     .expect("Cannot get filter 'in'")
-                //     .source()
-                //     .add(&frame)?;
-                // graph
-                //     .get("out")
-                //     .expect("Cannot get filter 'out'")
-                //     .sink()
-                //     .frame(&mut frame)?;
-                // log::info!("[filtered frame] pts: {:?}", frame.pts());
+    #[test]
+    fn remux_access_units_by_marker() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(8);
+        let (h264_sender, mut h264_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let nal = |byte: u8| Bytes::from(vec![0, 0, 0, 1, 0x41, byte]);
+        // 40ms 带 marker 的包丢失，时间戳变化时结束访问单元；120ms 在源结束时输出
+        let packets = [
+            (0, 1, false),
+            (0, 2, true),
+            (40, 3, false),
+            (80, 4, false),
+            (120, 5, false),
+        ];
+        let record = AVCDecoderConfigurationRecord::from_annexb(&parameter_sets(true)).unwrap();
+        let configuration = H264Data::configuration(record.to_annexb().into(), record);
+        assert!(sender.try_send(configuration).is_ok());
+        for (timestamp, byte, marker) in packets {
+            let packet = H264Data::nal(timestamp, timestamp, nal(byte), marker);
+            assert!(sender.try_send(packet).is_ok());
+        }
+        drop(sender);
+
+        remux(receiver, h264_sender, None).unwrap();
+        let mut units = Vec::new();
+        while let Ok(packet) = h264_receiver.try_recv() {
+            if let VideoPacket::Avc(H264Data::Data { dts, data, .. }) = packet {
+                units.push((dts, data.to_vec()));
             }
         }
+        let mut first = nal(1).to_vec();
+        first.extend_from_slice(&nal(2));
+        assert_eq!(
+            units,
+            vec![
+                (0, first),
+                (40, nal(3).to_vec()),
+                (80, nal(4).to_vec()),
+                (120, nal(5).to_vec()),
+            ]
+        );
     }
 
-    Ok(())
+    #[test]
+    fn remux_drops_frames_before_sequence_header() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(8);
+        let (h264_sender, mut h264_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let frame = Bytes::from_static(&[0, 0, 0, 1, 0x41, 0x9a]);
+        let record = AVCDecoderConfigurationRecord::from_annexb(&parameter_sets(true)).unwrap();
+        // SPS 被截断的配置记录应被跳过而不是结束转封装
+        let mut invalid = record.clone();
+        invalid.sequence_parameter_sets[0].1.truncate(2);
+        let packets = [
+            H264Data::data(0, 0, frame.clone()),
+            H264Data::configuration(invalid.to_annexb().into(), invalid),
+            H264Data::data(40, 40, frame.clone()),
+            H264Data::configuration(record.to_annexb().into(), record),
+            H264Data::data(80, 80, frame),
+        ];
+        for packet in packets {
+            assert!(sender.try_send(packet).is_ok());
+        }
+        drop(sender);
+
+        remux(receiver, h264_sender, None).unwrap();
+        let mut packets = Vec::new();
+        while let Ok(VideoPacket::Avc(packet)) = h264_receiver.try_recv() {
+            packets.push(match packet {
+                H264Data::Configuration { .. } => None,
+                H264Data::Data { dts, .. } => Some(dts),
+            });
+        }
+        assert_eq!(packets, vec![None, Some(80)]);
+    }
+
+    /// 在阻塞线程中解码全部数据，返回解码结果
+    async fn decode_all(
+        packets: Vec<Bytes>,
+        slot: FrameSlot,
+        keyframe_request: Arc<Notify>,
+    ) -> anyhow::Result<()> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(packets.len());
+        for packet in packets {
+            assert!(sender.try_send(H264Data::data(0, 0, packet)).is_ok());
+        }
+        drop(sender);
+        tokio::task::spawn_blocking(move || {
+            decode(
+                "reset".to_string(),
+                receiver,
+                slot,
+                keyframe_request,
+                Default::default(),
+            )
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn encoder_applies_settings() {
+        let canvas = Canvas {
+            width: 64,
+            height: 64,
+            ..Canvas::default()
+        };
+        let settings = EncoderSettings {
+            keyframe_interval: 5,
+            rate_control: RateControl::Quality,
+            skip_frame: false,
+            ..EncoderSettings::default()
+        };
+        let mut encoder = new_encoder(&canvas, &settings).unwrap();
+
+        let mut params = SEncParamExt::default();
+        let result = unsafe {
+            encoder.raw_api().get_option(
+                ENCODER_OPTION_SVC_ENCODE_PARAM_EXT,
+                addr_of_mut!(params).cast(),
+            )
+        };
+        assert_eq!(result, 0);
+        assert_eq!(params.iRCMode, RC_QUALITY_MODE);
+        assert_eq!(params.uiIntraPeriod, 5);
+
+        // 每 5 帧一个 IDR
+        let idr: Vec<bool> = (0..12u8)
+            .map(|i| {
+                let frame = YuvFrame::new(64, 64, (i * 16, 128, 128));
+                encoder.encode(&frame).unwrap().frame_type() == FrameType::IDR
+            })
+            .collect();
+        let expected: Vec<bool> = (0..12).map(|i| i % 5 == 0).collect();
+        assert_eq!(idr, expected);
+    }
+
+    fn key_frame(frame_mbs_only: bool, luma: u8) -> Bytes {
+        let mut data = parameter_sets(frame_mbs_only);
+        data.extend(picture(SliceType::I, 0, 0, luma));
+        Bytes::from(data)
+    }
+
+    #[tokio::test]
+    async fn reset_after_decode_error_until_keyframe() {
+        let mut truncated = picture(SliceType::P, 1, 1, 90);
+        truncated.truncate(truncated.len() - 200);
+        let packets = vec![
+            key_frame(true, 50),
+            truncated.into(),
+            // 重置后等待关键帧，跳过 P 帧
+            picture(SliceType::P, 1, 1, 90).into(),
+            key_frame(true, 130),
+            key_frame(false, 170),
+        ];
+        let slot = FrameSlot::default();
+        let keyframe_request = Arc::new(Notify::new());
+        let result = decode_all(packets, slot.clone(), keyframe_request.clone()).await;
+
+        // 不支持的带内 SPS 不再重置重试
+        assert!(result.unwrap_err().to_string().contains("Interlaced"));
+        keyframe_request.notified().await;
+        assert_eq!(slot.latest().unwrap().0.y()[0], 130);
+    }
+
+    #[tokio::test]
+    async fn recover_from_corrupt_sps() {
+        // 只保留 profile_idc 的 SPS 无法解析
+        let mut corrupt = vec![0, 0, 0, 1, 0x67, 77];
+        corrupt.extend(picture(SliceType::I, 0, 0, 90));
+        let packets = vec![key_frame(true, 50), corrupt.into(), key_frame(true, 130)];
+        let slot = FrameSlot::default();
+        let keyframe_request = Arc::new(Notify::new());
+        let result = decode_all(packets, slot.clone(), keyframe_request.clone()).await;
+
+        assert!(result.is_ok());
+        keyframe_request.notified().await;
+        assert_eq!(slot.latest().unwrap().0.y()[0], 130);
+    }
 }
-//
-// #[allow(dead_code)]
-// fn build_frame(config: &FilterConfig) -> Frame {
-//     unsafe {
-//         let mut frame = Frame::empty();
-//         let av_frame = frame.as_mut_ptr();
-//         (*av_frame).width = config.width;
-//         (*av_frame).height = config.height;
-//         (*av_frame).format = INPUT_FORMAT as i32;
-//         assert_eq!(av_frame_get_buffer(av_frame, 1), 0);
-//         assert_eq!(av_frame_make_writable(av_frame), 0);
-//         frame
-//     }
-// }
-//
-// #[allow(dead_code)]
-// fn fill_frame<YUV: YUVSource>(yuv: &YUV, frame: &mut Frame) {
-//     unsafe {
-//         let av_frame = frame.as_mut_ptr();
-//
-//         av_image_copy_plane(
-//             (*av_frame).data[0],
-//             (*av_frame).linesize[0],
-//             yuv.y().as_ptr(),
-//             yuv.y_stride(),
-//             yuv.width(),
-//             yuv.height(),
-//         );
-//         av_image_copy_plane(
-//             (*av_frame).data[1],
-//             (*av_frame).linesize[1],
-//             yuv.u().as_ptr(),
-//             yuv.u_stride(),
-//             yuv.width() / 2,
-//             yuv.height(),
-//         );
-//         av_image_copy_plane(
-//             (*av_frame).data[2],
-//             (*av_frame).linesize[2],
-//             yuv.v().as_ptr(),
-//             yuv.v_stride(),
-//             yuv.width() / 2,
-//             yuv.height(),
-//         );
-//     }
-// }
-//
-// #[derive(Debug, Deserialize, Serialize)]
-// pub struct FilterConfig {
-//     width: i32,
-//     height: i32,
-//     #[serde(with = "rational_serde")]
-//     time_base: Rational,
-// }
-//
-// impl Default for FilterConfig {
-//     fn default() -> Self {
-//         Self {
-//             width: 320,
-//             height: 240,
-//             time_base: Rational::new(1, 90000),
-//         }
-//     }
-// }
-//
-// #[allow(dead_code)]
-// pub fn build_filter_chain(config: &FilterConfig) -> anyhow::Result<Graph> {
-//     let buffer = find_filter("buffer")?;
-//     let scale = find_filter("scale")?;
-//     let overlay = find_filter("overlay")?;
-//     let crop = find_filter("crop")?;
-//     let buffer_sink = find_filter("buffersink")?;
-//
-//     println!(
-//         "filters: {}, {}, {}, {}, {}",
-//         buffer.name(),
-//         scale.name(),
-//         overlay.name(),
-//         crop.name(),
-//         buffer_sink.name()
-//     );
-//
-//     let mut graph = ffmpeg::filter::Graph::new();
-//
-//     let buffer_args = format!(
-//         "video_size={}x{}:pix_fmt={}:time_base={}:pixel_aspect={}/{}",
-//         config.width, config.height, INPUT_FORMAT as i32, config.time_base, 1, 1
-//     );
-//
-//     graph.add(&buffer, "in", &buffer_args)?;
-//     graph.add(&buffer_sink, "out", "")?;
-//
-//     graph.output("in", 0)?.input("out", 0)?.parse("null")?;
-//     graph.validate()?;
-//
-//     Ok(graph)
-// }
-
-// #[allow(dead_code)]
-// pub fn find_filter(name: &str) -> anyhow::Result<Filter> {
-//     ffmpeg::filter::find(name).ok_or_else(|| anyhow::anyhow!("Filter '{}' not found", name))
-// }
-//
-// mod rational_serde {
-//     use ffmpeg::Rational;
-//     use serde::de::Error;
-//     use serde::{Deserialize, Deserializer, Serializer};
-//
-//     pub fn serialize<S>(rational: &Rational, serializer: S) -> Result<S::Ok, S::Error>
-//     where
-//         S: Serializer,
-//     {
-//         let s = format!("{}", rational);
-//         serializer.serialize_str(&s)
-//     }
-//
-//     // The signature of a deserialize_with function must follow the pattern:
-//     //
-//     //    fn deserialize<'de, D>(D) -> Result<T, D::Error>
-//     //    where
-//     //        D: Deserializer<'de>
-//     //
-//     // although it may also be generic over the output types T.
-//     pub fn deserialize<'de, D>(deserializer: D) -> Result<Rational, D::Error>
-//     where
-//         D: Deserializer<'de>,
-//     {
-//         let s = String::deserialize(deserializer)?;
-//         let a = s.split('/').collect::<Vec<&str>>();
-//         if a.len() != 2 {
-//             return Err(D::Error::custom("Invalid rational string"));
-//         }
-//         match (a[0].parse::<i32>(), a[1].parse::<i32>()) {
-//             (Ok(n), Ok(d)) => Ok(Rational::new(n, d)),
-//             _ => return Err(D::Error::custom("Failed to parse integer")),
-//         }
-//     }
-// }
@@ -0,0 +1,1725 @@
+use crate::filter::{FilterRegistry, VideoFilter};
+use crate::param::{
+    Anchor, Border, Canvas, Color, Easing, FilterMode, ImageOverlay, Layout, Mask, Placeholder,
+    Region, Rotation, TextOverlay, Transition, VideoPosition, MAX_HOLD_SECS, MAX_LEAVE_SECS,
+    MAX_TEXT_SIZE,
+};
+use crate::speaker::SpeakerDetector;
+use crate::template;
+use crate::text::TextRenderer;
+use crate::yuv::{AlphaMask, Rect, YuvFrame};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 解码帧及其到达时间
+pub type TimedFrame = (Arc<YuvFrame>, Instant);
+
+/// 某一路源的最新解码帧，由解码线程写入，输出时钟读取
+#[derive(Debug, Clone, Default)]
+pub struct FrameSlot(Arc<Mutex<Option<TimedFrame>>>);
+
+impl FrameSlot {
+    pub fn put(&self, frame: YuvFrame) {
+        self.put_at(frame, Instant::now());
+    }
+
+    /// 写入帧并指定到达时间
+    fn put_at(&self, frame: YuvFrame, arrived: Instant) {
+        *self.0.lock().unwrap() = Some((Arc::new(frame), arrived));
+    }
+
+    pub fn latest(&self) -> Option<TimedFrame> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+struct Layer {
+    position: VideoPosition,
+    slot: FrameSlot,
+    /// 源中断后保持最后一帧的时长
+    hold: Duration,
+    /// 按源尺寸缓存的源区域及目标区域，源分辨率变化时重新计算
+    placement: Option<((usize, usize), Rect, Rect)>,
+    /// 按 tile 尺寸渲染好的占位画面
+    placeholder: Option<YuvFrame>,
+    /// 形状遮罩与不透明度合成的 tile 遮罩，完全不透明的矩形为 None
+    alpha: Option<AlphaMask>,
+    showing_placeholder: bool,
+    /// 布局切换的过渡动画，结束后为 None
+    tween: Option<Tween>,
+    /// 最近一帧的区域及不透明度，布局再次变化时作为过渡的起点
+    current: (Rect, f32),
+    /// 已从布局中移除，淡出结束后删除
+    leaving: bool,
+    /// 最近一帧旋转、翻转、抠像及调色后的结果
+    processed: Option<Processed>,
+    /// 按 tile 配置创建的滤镜链及滤镜名称
+    filters: Vec<(String, Box<dyn VideoFilter>)>,
+}
+
+/// 处理后的画面，抠像时带透明度遮罩
+struct Processed {
+    source: Arc<YuvFrame>,
+    frame: Arc<YuvFrame>,
+    matte: Option<AlphaMask>,
+}
+
+/// 从某一区域及不透明度过渡到 tile 的目标值
+struct Tween {
+    from: Rect,
+    from_opacity: f32,
+    /// 开始时的输出时间戳（毫秒），首次绘制时确定
+    start: Option<u32>,
+    duration: u32,
+    easing: Easing,
+}
+
+impl Tween {
+    fn new((from, from_opacity): (Rect, f32), transition: &Transition) -> Self {
+        Self {
+            from,
+            from_opacity,
+            start: None,
+            duration: (transition.duration_secs.max(0.0) * 1000.0) as u32,
+            easing: transition.easing,
+        }
+    }
+}
+
+impl Layer {
+    fn new(position: VideoPosition, slot: FrameSlot) -> Self {
+        let opacity = position.opacity.clamp(0.0, 1.0);
+        let alpha = tile_alpha(
+            position.mask,
+            position.width as usize,
+            position.height as usize,
+            opacity,
+        );
+        let hold = Duration::try_from_secs_f64(position.fallback.hold_secs.max(0.0))
+            .unwrap_or_else(|_| Duration::from_secs_f64(MAX_HOLD_SECS));
+        let mut layer = Self {
+            position,
+            slot,
+            hold,
+            placement: None,
+            placeholder: None,
+            alpha,
+            // 首帧到达前即显示占位画面
+            showing_placeholder: true,
+            tween: None,
+            current: (Rect::default(), opacity),
+            leaving: false,
+            processed: None,
+            filters: Vec::new(),
+        };
+        layer.current.0 = layer.target();
+        layer
+    }
+
+    /// 过渡结束时的不透明度
+    fn opacity(&self) -> f32 {
+        if self.leaving {
+            0.0
+        } else {
+            self.position.opacity.clamp(0.0, 1.0)
+        }
+    }
+
+    /// 按输出时间戳推进过渡动画，返回本帧的区域及不透明度
+    fn animate(&mut self, timestamp: u32) -> (Rect, f32) {
+        let (target, opacity) = (self.target(), self.opacity());
+        let current = match &mut self.tween {
+            None => (target, opacity),
+            Some(tween) => {
+                let start = *tween.start.get_or_insert(timestamp);
+                let elapsed = timestamp.saturating_sub(start);
+                let t = elapsed as f32 / tween.duration.max(1) as f32;
+                let p = tween.easing.apply(t);
+                let lerp =
+                    |from: isize, to: isize| from + ((to - from) as f32 * p).round() as isize;
+                let rect = Rect::new(
+                    lerp(tween.from.x, target.x),
+                    lerp(tween.from.y, target.y),
+                    lerp(tween.from.width as isize, target.width as isize) as usize,
+                    lerp(tween.from.height as isize, target.height as isize) as usize,
+                );
+                let current = (
+                    rect,
+                    tween.from_opacity + (opacity - tween.from_opacity) * p,
+                );
+                if elapsed >= tween.duration {
+                    self.tween = None;
+                }
+                current
+            }
+        };
+        self.current = current;
+        current
+    }
+
+    fn target(&self) -> Rect {
+        Rect::new(
+            self.position.x as isize,
+            self.position.y as isize,
+            self.position.width as usize,
+            self.position.height as usize,
+        )
+    }
+
+    fn placement(&mut self, frame: &YuvFrame) -> (Rect, Rect) {
+        match self.placement {
+            Some((size, src_rect, dst_rect)) if size == frame.dimensions() => (src_rect, dst_rect),
+            _ => {
+                let source = source_rect(&self.position, frame.rect());
+                let (src_rect, dst_rect) = placement(self.position.mode, source, self.target());
+                let (width, height) = frame.dimensions();
+                log::info!(
+                    "Layer {} source size: {}x{}, draw {:?} into {:?}",
+                    self.position.id,
+                    width,
+                    height,
+                    src_rect,
+                    dst_rect
+                );
+                self.placement = Some((frame.dimensions(), src_rect, dst_rect));
+                (src_rect, dst_rect)
+            }
+        }
+    }
+
+    /// 源在保持时长内有新帧则返回该帧，否则应显示占位画面
+    fn current_frame(&mut self, now: Instant) -> Option<Arc<YuvFrame>> {
+        let frame = match self.slot.latest() {
+            Some((frame, arrived)) if now.saturating_duration_since(arrived) <= self.hold => {
+                Some(frame)
+            }
+            _ => None,
+        };
+        if frame.is_none() != self.showing_placeholder {
+            self.showing_placeholder = frame.is_none();
+            if self.showing_placeholder {
+                log::warn!("Layer {} has no frame, show placeholder", self.position.id);
+            } else {
+                log::info!("Layer {} source resumed", self.position.id);
+            }
+        }
+        frame
+    }
+
+    /// 对新到达的源帧依次旋转、翻转、抠像、调色及应用滤镜链
+    ///
+    /// 没有滤镜时同一帧复用上次的结果，滤镜可能随时间变化，每次输出都重新处理
+    fn process(&mut self, frame: Option<Arc<YuvFrame>>, timestamp: u32) -> Option<Arc<YuvFrame>> {
+        let position = &self.position;
+        let transformed = position.rotation != Rotation::None
+            || position.flip_horizontal
+            || position.flip_vertical;
+        let adjustment = position
+            .color_adjustment
+            .filter(|adjustment| !adjustment.is_identity());
+        let frame = match frame {
+            Some(frame)
+                if transformed
+                    || position.chroma_key.is_some()
+                    || adjustment.is_some()
+                    || !self.filters.is_empty() =>
+            {
+                frame
+            }
+            frame => {
+                self.processed = None;
+                return frame;
+            }
+        };
+        if let Some(processed) = &self.processed {
+            if Arc::ptr_eq(&processed.source, &frame) && self.filters.is_empty() {
+                return Some(processed.frame.clone());
+            }
+        }
+        let mut output = if transformed {
+            let mut output = frame.rotated(position.rotation);
+            output.flip(position.flip_horizontal, position.flip_vertical);
+            output
+        } else {
+            YuvFrame::clone(&frame)
+        };
+        let matte = position.chroma_key.map(|key| {
+            let (_, u, v) = key.color.to_yuv();
+            output.chroma_key((u, v), key.similarity, key.smoothness, key.spill)
+        });
+        // 抠像按原始色彩判断，之后再调色
+        if let Some(adjustment) = &adjustment {
+            output.adjust_color(adjustment);
+        }
+        for (name, filter) in &mut self.filters {
+            if let Err(e) = filter.apply(&mut output, timestamp) {
+                log::warn!("Layer {} filter {} failed: {}", self.position.id, name, e);
+            }
+        }
+        let output = Arc::new(output);
+        self.processed = Some(Processed {
+            source: frame,
+            frame: output.clone(),
+            matte,
+        });
+        Some(output)
+    }
+
+    /// 首次需要时渲染占位画面
+    fn prepare_placeholder(&mut self) {
+        if self.placeholder.is_some() {
+            return;
+        }
+        let (width, height) = (self.position.width as usize, self.position.height as usize);
+        let placeholder = &self.position.fallback.placeholder;
+        self.placeholder = Some(
+            render_placeholder(placeholder, width, height).unwrap_or_else(|e| {
+                log::error!("Failed to render placeholder {:?}: {}", placeholder, e);
+                YuvFrame::new(width, height, Color::default().to_yuv())
+            }),
+        );
+    }
+}
+
+/// 预先渲染好的带透明度的叠加层，每帧按原尺寸混合到画布上
+struct Overlay {
+    frame: YuvFrame,
+    alpha: AlphaMask,
+    x: isize,
+    y: isize,
+}
+
+impl Overlay {
+    fn draw(&self, output: &mut YuvFrame) {
+        let (width, height) = self.frame.dimensions();
+        let rect = Rect::new(self.x, self.y, width, height);
+        output.draw_blended(
+            &self.frame,
+            self.frame.rect(),
+            rect,
+            &self.alpha,
+            (self.x, self.y),
+        );
+    }
+}
+
+/// 文字叠加层，滚动字幕按输出时间戳在所属区域内循环滚动
+struct TextLayer {
+    overlay: Overlay,
+    /// 所属区域及滚动速度（像素/秒）
+    scroll: Option<(Rect, f32)>,
+}
+
+impl TextLayer {
+    fn draw(&self, output: &mut YuvFrame, timestamp: u32) {
+        let (area, speed) = match self.scroll {
+            Some(scroll) => scroll,
+            None => return self.overlay.draw(output),
+        };
+        let overlay = &self.overlay;
+        let (width, height) = overlay.frame.dimensions();
+        // 空文字且无内边距时附加到宽度为 0 的 tile 上，没有可绘制的内容
+        let cycle = area.width + width;
+        if cycle == 0 {
+            return;
+        }
+        // 从区域右侧进入，完全移出左侧后重新开始
+        let offset = (timestamp as f64 * speed as f64 / 1000.0) as usize % cycle;
+        let x = (area.x + area.width as isize - offset as isize) & !1;
+        let left = x.max(area.x);
+        let right = (x + width as isize).min(area.x + area.width as isize);
+        if right <= left {
+            return;
+        }
+        let visible = (right - left) as usize;
+        output.draw_blended(
+            &overlay.frame,
+            Rect::new(left - x, 0, visible, height),
+            Rect::new(left, overlay.y, visible, height),
+            &overlay.alpha,
+            (x, overlay.y),
+        );
+    }
+}
+
+/// 图片叠加层，配置不变时复用已转换的画面
+struct ImageLayer {
+    image: ImageOverlay,
+    /// 转换后的画面，读取完成前为 None，不绘制
+    overlay: Option<Overlay>,
+    /// 后台线程读取图片的结果
+    loading: Option<mpsc::Receiver<anyhow::Result<Overlay>>>,
+}
+
+impl ImageLayer {
+    /// 在后台线程读取图片，避免阻塞输出时钟，缩放后的尺寸不能超过 canvas
+    fn spawn(image: &ImageOverlay, canvas: (usize, usize)) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let config = image.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(load_image(&config, canvas));
+        });
+        Self {
+            image: image.clone(),
+            overlay: None,
+            loading: Some(receiver),
+        }
+    }
+
+    /// 取回后台读取的结果，wait 为 true 时等待读取完成
+    fn poll(&mut self, wait: bool) {
+        let result = match &self.loading {
+            None => return,
+            Some(receiver) if wait => receiver.recv().map_err(|_| TryRecvError::Disconnected),
+            Some(receiver) => receiver.try_recv(),
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("Image loader exited")),
+        };
+        self.loading = None;
+        match result {
+            Ok(overlay) => self.overlay = Some(overlay),
+            Err(e) => log::error!("Failed to load image overlay {}: {}", self.image.id, e),
+        }
+    }
+
+    fn draw(&self, output: &mut YuvFrame) {
+        if let Some(overlay) = &self.overlay {
+            overlay.draw(output);
+        }
+    }
+}
+
+/// 读取图片并按配置缩放，与不透明度一起转换为 YUVA
+fn load_image(
+    image: &ImageOverlay,
+    (max_width, max_height): (usize, usize),
+) -> anyhow::Result<Overlay> {
+    let (frame, alpha) = YuvFrame::open_rgba(&image.path)?;
+    let (source_width, source_height) = frame.dimensions();
+    anyhow::ensure!(
+        source_width > 0 && source_height > 0,
+        "Empty image {}",
+        image.path.display()
+    );
+    let (width, height) = match (image.width, image.height) {
+        (Some(width), Some(height)) => (width as usize, height as usize),
+        (Some(width), None) => {
+            let width = width as usize;
+            (width, (source_height * width / source_width).max(1))
+        }
+        (None, Some(height)) => {
+            let height = height as usize;
+            ((source_width * height / source_height).max(1), height)
+        }
+        (None, None) => (source_width, source_height),
+    };
+    anyhow::ensure!(
+        width <= max_width && height <= max_height,
+        "Image size {}x{} exceeds the canvas {}x{}",
+        width,
+        height,
+        max_width,
+        max_height
+    );
+    let (frame, mut alpha) = if (width, height) == (source_width, source_height) {
+        (frame, alpha)
+    } else {
+        let mut scaled = YuvFrame::new(width, height, Color::default().to_yuv());
+        scaled.draw_scaled(&frame, frame.rect(), scaled.rect());
+        (scaled, alpha.scaled(width, height))
+    };
+    alpha.fade(image.opacity);
+    log::info!(
+        "Load image overlay {} from {}, {}x{} -> {}x{}",
+        image.id,
+        image.path.display(),
+        source_width,
+        source_height,
+        width,
+        height
+    );
+    Ok(Overlay {
+        frame,
+        alpha,
+        x: image.x as isize,
+        y: image.y as isize,
+    })
+}
+
+/// 合成后端，由 [`crate::codec::encode`] 按输出时钟驱动
+///
+/// 默认为纯 Rust 的 [`Compositor`]，启用 `ffmpeg` 特性时可改用 ffmpeg 滤镜图
+pub trait Compose: Send {
+    fn canvas(&self) -> &Canvas;
+
+    fn set_layout(&mut self, layout: &Layout);
+
+    fn compose(&mut self, timestamp: u32) -> &YuvFrame;
+}
+
+impl Compose for Compositor {
+    fn canvas(&self) -> &Canvas {
+        Compositor::canvas(self)
+    }
+
+    fn set_layout(&mut self, layout: &Layout) {
+        Compositor::set_layout(self, layout)
+    }
+
+    fn compose(&mut self, timestamp: u32) -> &YuvFrame {
+        Compositor::compose(self, timestamp)
+    }
+}
+
+/// 将各路源按位置缩放合成到固定尺寸的画布上
+pub struct Compositor {
+    canvas: Canvas,
+    output: YuvFrame,
+    layers: Vec<Layer>,
+    /// 按 id 索引的各路源，布局中的 tile 按 id 引用
+    sources: HashMap<String, FrameSlot>,
+    /// 图片叠加，按 layer 排序
+    images: Vec<ImageLayer>,
+    /// 文字叠加，绘制在所有视频之上
+    texts: Vec<TextLayer>,
+    /// 已加载的字体，按路径缓存
+    fonts: HashMap<PathBuf, TextRenderer>,
+    /// 当前布局
+    layout: Layout,
+    /// 布局模板当前使用的活跃源
+    active: Vec<String>,
+    /// 发言人检测，用于主画面跟随发言人及高亮
+    speaker: Option<SpeakerDetector>,
+    active_speaker: Option<String>,
+    /// 当前发言人 tile 的边框
+    border: Option<Overlay>,
+    /// tile 滤镜链中可引用的滤镜
+    filters: FilterRegistry,
+}
+
+impl Compositor {
+    /// tile 的滤镜链可引用 filters 中注册的滤镜
+    pub fn new(canvas: Canvas, filters: FilterRegistry) -> Self {
+        let output = YuvFrame::new(
+            canvas.width as usize,
+            canvas.height as usize,
+            canvas.background.to_yuv(),
+        );
+        Self {
+            canvas,
+            output,
+            layers: Vec::new(),
+            sources: HashMap::new(),
+            images: Vec::new(),
+            texts: Vec::new(),
+            fonts: HashMap::new(),
+            layout: Layout::default(),
+            active: Vec::new(),
+            speaker: None,
+            active_speaker: None,
+            border: None,
+            filters,
+        }
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    /// 注册一路源，布局中 id 相同的 tile 显示该源
+    pub fn add_source(&mut self, id: impl Into<String>, slot: FrameSlot) {
+        let id = id.into();
+        for layer in self.layers.iter_mut().filter(|l| l.position.id == id) {
+            layer.slot = slot.clone();
+        }
+        self.sources.insert(id, slot);
+    }
+
+    /// 启用发言人检测，布局可据此切换主画面或高亮发言人
+    pub fn set_speaker_detector(&mut self, detector: SpeakerDetector) {
+        self.speaker = Some(detector);
+    }
+
+    /// 设置新布局，有模板时按当前活跃的源生成 tile
+    pub fn set_layout(&mut self, layout: &Layout) {
+        self.layout = layout.clone();
+        self.active = self.active_sources(Instant::now());
+        self.apply_layout();
+    }
+
+    /// 模板使用的活跃源：指定了源列表时按列表顺序，否则为所有源按 ID 排序
+    fn active_sources(&self, now: Instant) -> Vec<String> {
+        let template = match &self.layout.template {
+            Some(template) => template,
+            None => return Vec::new(),
+        };
+        let leave = Duration::try_from_secs_f64(template.leave_secs.max(0.0))
+            .unwrap_or_else(|_| Duration::from_secs_f64(MAX_LEAVE_SECS));
+        let is_active = |id: &String| {
+            self.sources
+                .get(id)
+                .and_then(|slot| slot.latest())
+                .is_some_and(|(_, arrived)| now.saturating_duration_since(arrived) <= leave)
+        };
+        if template.sources.is_empty() {
+            let mut active: Vec<String> = self
+                .sources
+                .keys()
+                .filter(|id| is_active(id))
+                .cloned()
+                .collect();
+            active.sort();
+            active
+        } else {
+            template
+                .sources
+                .iter()
+                .filter(|id| is_active(id))
+                .cloned()
+                .collect()
+        }
+    }
+
+    /// 按当前布局重建各 tile，layer 大的覆盖在上层，同层按布局中的顺序
+    fn apply_layout(&mut self) {
+        let mut layout = self.layout.clone();
+        if let Some(template) = &mut layout.template {
+            if template.follow_speaker && self.active_speaker.is_some() {
+                template.main = self.active_speaker.clone();
+            }
+            let mut videos = template::generate(template, &self.canvas, &self.active);
+            videos.append(&mut layout.videos);
+            layout.videos = videos;
+        }
+        let layout = &layout;
+
+        // 有过渡动画时，同一源从原来的区域移动到新位置，新源淡入，移除的源淡出
+        let transition = &layout.transition;
+        let animate = transition.duration_secs > 0.0;
+        let mut previous = std::mem::take(&mut self.layers);
+        let mut layers: Vec<Layer> = layout
+            .videos
+            .iter()
+            .map(|position| {
+                let slot = self.sources.get(&position.id).cloned().unwrap_or_else(|| {
+                    log::warn!("Layout references unknown source {}", position.id);
+                    FrameSlot::default()
+                });
+                let mut layer = Layer::new(position.clone(), slot);
+                // 滤镜配置不变时沿用原有实例，保留滤镜的跨帧状态
+                let reused = previous
+                    .iter_mut()
+                    .find(|old| {
+                        old.position.id == position.id
+                            && !old.leaving
+                            && !old.filters.is_empty()
+                            && old.position.filters == position.filters
+                    })
+                    .map(|old| std::mem::take(&mut old.filters));
+                layer.filters = match reused {
+                    Some(filters) => filters,
+                    None => position
+                        .filters
+                        .iter()
+                        .filter_map(|config| match self.filters.build(config) {
+                            Ok(filter) => Some((config.name.clone(), filter)),
+                            Err(e) => {
+                                log::error!("Layer {}: {}", position.id, e);
+                                None
+                            }
+                        })
+                        .collect(),
+                };
+                if animate {
+                    let old = previous
+                        .iter()
+                        .position(|old| old.position.id == position.id && !old.leaving)
+                        .map(|index| previous.swap_remove(index));
+                    let from = match &old {
+                        Some(old) => old.current,
+                        None => (layer.target(), 0.0),
+                    };
+                    if from != layer.current {
+                        layer.tween = Some(Tween::new(from, transition));
+                    }
+                    if let Some(old) = old {
+                        layer.showing_placeholder = old.showing_placeholder;
+                    }
+                }
+                layer
+            })
+            .collect();
+        if animate {
+            for mut old in previous {
+                old.leaving = true;
+                old.tween = Some(Tween::new(old.current, transition));
+                layers.push(old);
+            }
+        }
+        layers.sort_by_key(|layer| layer.position.layer);
+        self.layers = layers;
+
+        let canvas = (self.canvas.width as usize, self.canvas.height as usize);
+        let mut previous = std::mem::take(&mut self.images);
+        let mut images: Vec<ImageLayer> = layout
+            .images
+            .iter()
+            .map(
+                |image| match previous.iter().position(|p| &p.image == image) {
+                    Some(index) => previous.swap_remove(index),
+                    None => ImageLayer::spawn(image, canvas),
+                },
+            )
+            .collect();
+        images.sort_by_key(|image| image.image.layer);
+        self.images = images;
+
+        self.border = match (&layout.speaker_border, &self.active_speaker) {
+            (Some(border), Some(speaker)) => layout
+                .videos
+                .iter()
+                .find(|video| &video.id == speaker)
+                .map(|video| border_overlay(video, border)),
+            _ => None,
+        };
+
+        let texts = layout
+            .texts
+            .iter()
+            .filter_map(|text| self.text_overlay(layout, text))
+            .collect();
+        self.texts = texts;
+    }
+
+    /// 渲染文字并按所属区域定位，字体加载失败或所属 tile 不存在时忽略该叠加层
+    fn text_overlay(&mut self, layout: &Layout, text: &TextOverlay) -> Option<TextLayer> {
+        let area = match &text.attach {
+            Some(id) => match layout.videos.iter().find(|video| &video.id == id) {
+                Some(video) => Rect::new(
+                    video.x as isize,
+                    video.y as isize,
+                    video.width as usize,
+                    video.height as usize,
+                ),
+                None => {
+                    log::warn!("Text {} attached to unknown video {}", text.id, id);
+                    return None;
+                }
+            },
+            None => Rect::new(
+                0,
+                0,
+                self.canvas.width as usize,
+                self.canvas.height as usize,
+            ),
+        };
+        let renderer = match self.fonts.get(&text.font) {
+            Some(renderer) => renderer,
+            None => match TextRenderer::load(&text.font) {
+                Ok(renderer) => self.fonts.entry(text.font.clone()).or_insert(renderer),
+                Err(e) => {
+                    log::error!("Failed to render text {}: {}", text.id, e);
+                    return None;
+                }
+            },
+        };
+        let (frame, alpha) = render_text(renderer, text);
+        let (width, height) = frame.dimensions();
+        let (x, y) = anchored(text.anchor, area, width, height);
+        // 对齐到偶数坐标，色度采样点与画布一致
+        Some(TextLayer {
+            overlay: Overlay {
+                frame,
+                alpha,
+                x: (x + text.x as isize) & !1,
+                y: (y + text.y as isize) & !1,
+            },
+            scroll: (text.scroll_speed > 0.0).then_some((area, text.scroll_speed)),
+        })
+    }
+
+    /// 按 layer 顺序混合各路源的最新帧及图片，尚未出帧或中断超时的源显示占位画面，最后绘制文字
+    ///
+    /// timestamp 为输出时钟的时间戳（毫秒），过渡动画按其逐帧推进
+    pub fn compose(&mut self, timestamp: u32) -> &YuvFrame {
+        self.compose_at(timestamp, Instant::now())
+    }
+
+    /// 以 now 作为当前时刻合成，源是否超时及活跃均据此判断
+    fn compose_at(&mut self, timestamp: u32, now: Instant) -> &YuvFrame {
+        let mut changed = false;
+        if self.layout.template.is_some() {
+            let active = self.active_sources(now);
+            if active != self.active {
+                log::info!("Active sources changed: {:?} -> {:?}", self.active, active);
+                self.active = active;
+                changed = true;
+            }
+        }
+        if let Some(detector) = &self.speaker {
+            let follow = self
+                .layout
+                .template
+                .as_ref()
+                .is_some_and(|t| t.follow_speaker);
+            if follow || self.layout.speaker_border.is_some() {
+                let speaker = detector.speaker(now);
+                if speaker != self.active_speaker {
+                    self.active_speaker = speaker;
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            self.apply_layout();
+        }
+        for image in &mut self.images {
+            image.poll(false);
+        }
+        self.output.fill(self.canvas.background.to_yuv());
+        let mut images = self.images.iter().peekable();
+        for layer in &mut self.layers {
+            while let Some(image) = images.next_if(|image| image.image.layer < layer.position.layer)
+            {
+                image.draw(&mut self.output);
+            }
+            let (target, opacity) = layer.animate(timestamp);
+            if target.is_empty() || opacity <= 0.0 {
+                continue;
+            }
+            // 过渡过程中按本帧的区域及不透明度重新计算遮罩和位置
+            let animated = (target, opacity) != (layer.target(), layer.opacity());
+            let frame = layer.current_frame(now);
+            let frame = layer.process(frame, timestamp);
+            let (src_rect, dst_rect) = match &frame {
+                Some(frame) if animated => {
+                    let source = source_rect(&layer.position, frame.rect());
+                    placement(layer.position.mode, source, target)
+                }
+                Some(frame) => layer.placement(frame),
+                None => {
+                    layer.prepare_placeholder();
+                    (layer.placeholder.as_ref().unwrap().rect(), target)
+                }
+            };
+            let animated_alpha = if animated {
+                tile_alpha(layer.position.mask, target.width, target.height, opacity)
+            } else {
+                None
+            };
+            let alpha = if animated {
+                animated_alpha.as_ref()
+            } else {
+                layer.alpha.as_ref()
+            };
+            if let (Some(frame), FilterMode::BlurFill) = (&frame, layer.position.mode) {
+                let source = source_rect(&layer.position, frame.rect());
+                let background = blurred_background(frame, source, target);
+                draw_layer(
+                    &mut self.output,
+                    alpha,
+                    target,
+                    &background,
+                    background.rect(),
+                    target,
+                );
+            }
+            let matte = layer
+                .processed
+                .as_ref()
+                .and_then(|processed| processed.matte.as_ref());
+            match (&frame, matte) {
+                (Some(frame), Some(matte)) => {
+                    // 抠像遮罩缩放到目标区域后与 tile 遮罩相乘
+                    let mut combined = alpha.cloned().unwrap_or_else(|| {
+                        AlphaMask::from_fn(target.width, target.height, |_, _| 255)
+                    });
+                    let matte = matte.scaled_region(src_rect, dst_rect.width, dst_rect.height);
+                    combined.multiply(&matte, dst_rect.x - target.x, dst_rect.y - target.y);
+                    self.output.draw_blended(
+                        frame,
+                        src_rect,
+                        dst_rect,
+                        &combined,
+                        (target.x, target.y),
+                    );
+                }
+                (Some(frame), None) => {
+                    draw_layer(&mut self.output, alpha, target, frame, src_rect, dst_rect)
+                }
+                (None, _) => {
+                    let placeholder = layer.placeholder.as_ref().unwrap();
+                    draw_layer(
+                        &mut self.output,
+                        alpha,
+                        target,
+                        placeholder,
+                        src_rect,
+                        dst_rect,
+                    )
+                }
+            }
+        }
+        // 淡出结束的 tile
+        self.layers
+            .retain(|layer| !(layer.leaving && layer.tween.is_none()));
+        for image in images {
+            image.draw(&mut self.output);
+        }
+        if let Some(border) = &self.border {
+            border.draw(&mut self.output);
+        }
+        for text in &self.texts {
+            text.draw(&mut self.output, timestamp);
+        }
+        &self.output
+    }
+}
+
+/// 将 src 绘制到画布，tile 有遮罩时按遮罩混合
+fn draw_layer(
+    output: &mut YuvFrame,
+    alpha: Option<&AlphaMask>,
+    target: Rect,
+    src: &YuvFrame,
+    src_rect: Rect,
+    dst_rect: Rect,
+) {
+    match alpha {
+        Some(alpha) => output.draw_blended(src, src_rect, dst_rect, alpha, (target.x, target.y)),
+        None => output.draw_scaled(src, src_rect, dst_rect),
+    }
+}
+
+/// BlurFill 的背景：先按 Crop 缩小到 tile 的 1/8 再模糊，放大绘制时进一步平滑
+fn blurred_background(frame: &YuvFrame, source: Rect, target: Rect) -> YuvFrame {
+    const DOWNSCALE: usize = 8;
+    let width = (target.width / DOWNSCALE).max(2);
+    let height = (target.height / DOWNSCALE).max(2);
+    let mut background = YuvFrame::new(width, height, Color::default().to_yuv());
+    let (src_rect, dst_rect) = placement(FilterMode::Crop, source, background.rect());
+    background.draw_scaled(frame, src_rect, dst_rect);
+    background.blur(2);
+    background
+}
+
+/// 按 tile 尺寸生成形状遮罩并乘以不透明度，边缘抗锯齿
+fn tile_alpha(
+    mask: Option<Mask>,
+    tile_width: usize,
+    tile_height: usize,
+    opacity: f32,
+) -> Option<AlphaMask> {
+    let opacity = opacity.clamp(0.0, 1.0);
+    let mask = mask.unwrap_or(Mask::Rectangle);
+    if opacity >= 1.0 && mask == Mask::Rectangle {
+        return None;
+    }
+
+    let (width, height) = (tile_width as f32, tile_height as f32);
+    let radius = match mask {
+        Mask::Rectangle => 0.0,
+        Mask::RoundedRectangle { radius } => (radius as f32).min(width / 2.0).min(height / 2.0),
+        Mask::Circle => width.min(height) / 2.0,
+    };
+    // 圆形为居中的正方形区域内的全圆角
+    let (left, top) = match mask {
+        Mask::Circle => ((width - radius * 2.0) / 2.0, (height - radius * 2.0) / 2.0),
+        _ => (0.0, 0.0),
+    };
+    let (right, bottom) = (width - left, height - top);
+
+    Some(AlphaMask::from_fn(tile_width, tile_height, |x, y| {
+        // 像素中心到圆角矩形的有向距离，内部为负
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let dx = (left + radius - px).max(px - (right - radius)).max(0.0);
+        let dy = (top + radius - py).max(py - (bottom - radius)).max(0.0);
+        let outside = if dx > 0.0 && dy > 0.0 {
+            (dx * dx + dy * dy).sqrt() - radius
+        } else {
+            let edge = (px - left).min(right - px).min(py - top).min(bottom - py);
+            -edge
+        };
+        let coverage = (0.5 - outside).clamp(0.0, 1.0);
+        (coverage * opacity * 255.0).round() as u8
+    }))
+}
+
+/// 按 tile 尺寸渲染占位画面
+fn render_placeholder(
+    placeholder: &Placeholder,
+    width: usize,
+    height: usize,
+) -> anyhow::Result<YuvFrame> {
+    match placeholder {
+        Placeholder::Color { color } => Ok(YuvFrame::new(width, height, color.to_yuv())),
+        Placeholder::Image { path } => {
+            let image = YuvFrame::open(path)?;
+            let mut frame = YuvFrame::new(width, height, Color::default().to_yuv());
+            let (src_rect, dst_rect) = placement(FilterMode::Fit, image.rect(), frame.rect());
+            frame.draw_scaled(&image, src_rect, dst_rect);
+            Ok(frame)
+        }
+        Placeholder::Text {
+            text,
+            font,
+            color,
+            background,
+        } => {
+            let renderer = TextRenderer::load(font)?;
+            let mut frame = YuvFrame::new(width, height, background.to_yuv());
+            let mask = renderer.rasterize(text, (height as f32 / 10.0).max(12.0));
+            let (mask_width, mask_height) = mask.dimensions();
+            let x = (width as isize - mask_width as isize) / 2;
+            let y = (height as isize - mask_height as isize) / 2;
+            frame.fill_masked(&mask, x, y, color.to_yuv());
+            Ok(frame)
+        }
+    }
+}
+
+/// 沿 tile 内侧绘制的纯色边框
+fn border_overlay(video: &VideoPosition, border: &Border) -> Overlay {
+    let (width, height) = (video.width as usize, video.height as usize);
+    let thickness = border.width as usize;
+    let alpha = AlphaMask::from_fn(width, height, |x, y| {
+        let inside =
+            x >= thickness && y >= thickness && x + thickness < width && y + thickness < height;
+        if inside {
+            0
+        } else {
+            255
+        }
+    });
+    Overlay {
+        frame: YuvFrame::new(width, height, border.color.to_yuv()),
+        alpha,
+        x: video.x as isize,
+        y: video.y as isize,
+    }
+}
+
+/// 将文字渲染为带透明度的画面，有背景框时整个框不透明
+fn render_text(renderer: &TextRenderer, text: &TextOverlay) -> (YuvFrame, AlphaMask) {
+    let mask = renderer.rasterize(&text.text, text.size.clamp(1.0, MAX_TEXT_SIZE));
+    let (text_width, text_height) = mask.dimensions();
+    let padding = text.padding as usize;
+    let width = (text_width + padding * 2).next_multiple_of(2);
+    let height = (text_height + padding * 2).next_multiple_of(2);
+    match text.background {
+        Some(background) => {
+            let mut frame = YuvFrame::new(width, height, background.to_yuv());
+            let offset = padding as isize;
+            frame.fill_masked(&mask, offset, offset, text.color.to_yuv());
+            (frame, AlphaMask::from_fn(width, height, |_, _| 255))
+        }
+        None => {
+            let frame = YuvFrame::new(width, height, text.color.to_yuv());
+            let alpha = AlphaMask::from_fn(width, height, |x, y| {
+                if x < padding || y < padding {
+                    0
+                } else {
+                    mask.alpha(x - padding, y - padding)
+                }
+            });
+            (frame, alpha)
+        }
+    }
+}
+
+/// 按对齐位置将 width x height 的叠加层放入区域，返回左上角坐标
+fn anchored(anchor: Anchor, area: Rect, width: usize, height: usize) -> (isize, isize) {
+    let free_x = area.width as isize - width as isize;
+    let free_y = area.height as isize - height as isize;
+    // 0、1、2 分别为靠左（上）、居中、靠右（下）
+    let (column, row) = match anchor {
+        Anchor::TopLeft => (0, 0),
+        Anchor::Top => (1, 0),
+        Anchor::TopRight => (2, 0),
+        Anchor::Left => (0, 1),
+        Anchor::Center => (1, 1),
+        Anchor::Right => (2, 1),
+        Anchor::BottomLeft => (0, 2),
+        Anchor::Bottom => (1, 2),
+        Anchor::BottomRight => (2, 2),
+    };
+    (area.x + free_x * column / 2, area.y + free_y * row / 2)
+}
+
+/// 按取景区域、放大倍数和平移计算实际使用的源区域，结果限制在源画面内
+pub(crate) fn source_rect(position: &VideoPosition, frame: Rect) -> Rect {
+    let (frame_width, frame_height) = (frame.width as f32, frame.height as f32);
+    let (x, y, width, height) = match position.source {
+        None => (0.0, 0.0, frame_width, frame_height),
+        Some(Region::Normalized {
+            x,
+            y,
+            width,
+            height,
+        }) => (
+            x * frame_width,
+            y * frame_height,
+            width * frame_width,
+            height * frame_height,
+        ),
+        Some(Region::Pixel {
+            x,
+            y,
+            width,
+            height,
+        }) => (x as f32, y as f32, width as f32, height as f32),
+    };
+    let x = x.clamp(0.0, frame_width);
+    let y = y.clamp(0.0, frame_height);
+    let width = width.clamp(0.0, frame_width - x);
+    let height = height.clamp(0.0, frame_height - y);
+
+    // 放大后区域缩小，平移在剩余范围内移动
+    let zoom = position.zoom.max(1.0);
+    let (zoomed_width, zoomed_height) = (width / zoom, height / zoom);
+    let x = x + (width - zoomed_width) * (position.pan_x.clamp(-1.0, 1.0) + 1.0) / 2.0;
+    let y = y + (height - zoomed_height) * (position.pan_y.clamp(-1.0, 1.0) + 1.0) / 2.0;
+    if frame.is_empty() {
+        return frame;
+    }
+    let x = (x.round() as usize).min(frame.width - 1);
+    let y = (y.round() as usize).min(frame.height - 1);
+    Rect::new(
+        frame.x + x as isize,
+        frame.y + y as isize,
+        (zoomed_width.round() as usize).clamp(1, frame.width - x),
+        (zoomed_height.round() as usize).clamp(1, frame.height - y),
+    )
+}
+
+/// 根据处理方式计算源区域及其在画布上的目标区域
+pub(crate) fn placement(mode: FilterMode, src: Rect, dst: Rect) -> (Rect, Rect) {
+    if src.is_empty() || dst.is_empty() {
+        return (src, Rect::default());
+    }
+    let (sw, sh, dw, dh) = (src.width, src.height, dst.width, dst.height);
+    // 源比目标更宽
+    let wider = sw * dh > dw * sh;
+    match mode {
+        FilterMode::Scale => (src, dst),
+        FilterMode::Fit | FilterMode::BlurFill => {
+            let (width, height) = if wider {
+                (dw, (sh * dw / sw).max(1))
+            } else {
+                ((sw * dh / sh).max(1), dh)
+            };
+            let x = dst.x + ((dw - width) / 2) as isize;
+            let y = dst.y + ((dh - height) / 2) as isize;
+            (src, Rect::new(x, y, width, height))
+        }
+        FilterMode::Crop => {
+            let (width, height) = if wider {
+                ((dw * sh / dh).max(1), sh)
+            } else {
+                (sw, (dh * sw / dw).max(1))
+            };
+            let x = src.x + ((sw - width) / 2) as isize;
+            let y = src.y + ((sh - height) / 2) as isize;
+            (Rect::new(x, y, width, height), dst)
+        }
+    }
+}
+
+/// 固定帧率的输出时钟，按墙上时间推进，处理不及时则跳过落后的帧
+pub struct OutputClock {
+    start: Instant,
+    fps: u32,
+    frame: u64,
+}
+
+impl OutputClock {
+    pub fn new(fps: u32) -> Self {
+        Self {
+            start: Instant::now(),
+            fps,
+            frame: 0,
+        }
+    }
+
+    fn deadline(&self, frame: u64) -> Duration {
+        Duration::from_nanos(frame * 1_000_000_000 / self.fps as u64)
+    }
+
+    /// 等待下一帧的输出时刻，返回其时间戳（毫秒）
+    pub fn tick(&mut self) -> u32 {
+        let elapsed = self.start.elapsed();
+        let deadline = self.deadline(self.frame);
+        if elapsed < deadline {
+            std::thread::sleep(deadline - elapsed);
+        } else {
+            let current = elapsed.as_nanos() as u64 * self.fps as u64 / 1_000_000_000;
+            if current > self.frame {
+                log::warn!("Output clock behind, drop {} frames", current - self.frame);
+                self.frame = current;
+            }
+        }
+        let timestamp = self.deadline(self.frame).as_millis() as u32;
+        self.frame += 1;
+        timestamp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::{Border, Fallback, FilterConfig, Template, TemplateKind};
+    use crate::yuv::rgb_to_yuv;
+    use openh264::formats::YUVSource;
+
+    fn canvas(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            fps: 25,
+            background: Default::default(),
+        }
+    }
+
+    fn position(layer: u32, rect: (u32, u32, u32, u32), mode: FilterMode) -> VideoPosition {
+        let mut position =
+            VideoPosition::new(format!("layer{}", layer), rect.0, rect.1, rect.2, rect.3);
+        position.layer = layer;
+        position.mode = mode;
+        position
+    }
+
+    /// 每个 tile 使用独立的源
+    fn compositor(canvas: Canvas, tiles: Vec<(VideoPosition, FrameSlot)>) -> Compositor {
+        let mut compositor = Compositor::new(canvas, FilterRegistry::default());
+        let mut layout = Layout::default();
+        for (position, slot) in tiles {
+            compositor.add_source(position.id.clone(), slot);
+            layout.videos.push(position);
+        }
+        compositor.set_layout(&layout);
+        compositor
+    }
+
+    #[test]
+    fn fit_keeps_aspect_ratio() {
+        let src = Rect::new(0, 0, 640, 480);
+        let dst = Rect::new(0, 0, 1280, 720);
+        let (src_rect, dst_rect) = placement(FilterMode::Fit, src, dst);
+        assert_eq!(src_rect, src);
+        assert_eq!(dst_rect, Rect::new(160, 0, 960, 720));
+    }
+
+    #[test]
+    fn crop_fills_target() {
+        let src = Rect::new(0, 0, 640, 480);
+        let dst = Rect::new(0, 0, 1280, 720);
+        let (src_rect, dst_rect) = placement(FilterMode::Crop, src, dst);
+        assert_eq!(src_rect, Rect::new(0, 60, 640, 360));
+        assert_eq!(dst_rect, dst);
+    }
+
+    #[test]
+    fn compose_scales_source_into_canvas() {
+        let slot = FrameSlot::default();
+        let mut compositor = compositor(
+            canvas(8, 4),
+            vec![(position(0, (0, 0, 8, 4), FilterMode::Fit), slot.clone())],
+        );
+        slot.put(YuvFrame::new(2, 2, (200, 100, 50)));
+
+        let output = compositor.compose(0);
+        let y = &output.y()[..8];
+        // 左右两侧为黑边，中间 4x4 为源画面
+        assert_eq!(y, &[16, 16, 200, 200, 200, 200, 16, 16]);
+        assert_eq!(&output.u()[..4], &[128, 100, 100, 128]);
+
+        // 源分辨率变化后重新计算位置，输出尺寸不变
+        slot.put(YuvFrame::new(4, 1, (100, 128, 128)));
+        let output = compositor.compose(0);
+        assert_eq!(output.dimensions(), (8, 4));
+        assert_eq!(&output.y()[..8], &[16; 8]);
+        assert_eq!(&output.y()[8..16], &[100; 8]);
+    }
+
+    #[test]
+    fn placeholder_replaces_missing_and_stalled_sources() {
+        let slot = FrameSlot::default();
+        let mut tile = position(0, (0, 0, 4, 2), FilterMode::Scale);
+        tile.fallback = Fallback {
+            hold_secs: 0.05,
+            placeholder: Placeholder::Color {
+                color: Color::white(),
+            },
+        };
+        let mut compositor = compositor(canvas(4, 2), vec![(tile, slot.clone())]);
+
+        let start = Instant::now();
+        // 首帧到达前显示占位画面
+        assert_eq!(compositor.compose_at(0, start).y(), &[235; 8]);
+
+        slot.put_at(YuvFrame::new(4, 2, (100, 128, 128)), start);
+        let now = start + Duration::from_millis(50);
+        assert_eq!(compositor.compose_at(0, now).y(), &[100; 8]);
+
+        // 超过保持时长后切换为占位画面
+        let now = start + Duration::from_millis(60);
+        assert_eq!(compositor.compose_at(0, now).y(), &[235; 8]);
+
+        // 超出范围的保持时长按上限处理
+        let mut tile = position(0, (0, 0, 4, 2), FilterMode::Scale);
+        tile.fallback.hold_secs = 1e300;
+        assert!(tile.fallback.validate().is_err());
+        let mut capped = self::compositor(canvas(4, 2), vec![(tile, slot.clone())]);
+        let now = start + Duration::from_secs(3600);
+        assert_eq!(capped.compose_at(0, now).y(), &[100; 8]);
+    }
+
+    #[test]
+    fn blend_layers_with_opacity_and_mask() {
+        let bottom = FrameSlot::default();
+        let top = FrameSlot::default();
+        let mut bubble = position(1, (0, 0, 8, 8), FilterMode::Scale);
+        bubble.opacity = 0.5;
+        bubble.mask = Some(Mask::Circle);
+        let mut compositor = compositor(
+            canvas(8, 8),
+            vec![
+                (bubble, top.clone()),
+                (position(0, (0, 0, 8, 8), FilterMode::Scale), bottom.clone()),
+            ],
+        );
+        bottom.put(YuvFrame::new(8, 8, (100, 128, 128)));
+        top.put(YuvFrame::new(8, 8, (200, 128, 128)));
+
+        let output = compositor.compose(0);
+        // 圆形外只有下层画面，圆心处按 50% 不透明度混合
+        assert_eq!(output.y()[0], 100);
+        assert_eq!(output.y()[4 * 8 + 4], 150);
+    }
+
+    #[test]
+    fn blur_fill_covers_bars_with_source() {
+        let slot = FrameSlot::default();
+        let mut compositor = compositor(
+            canvas(32, 16),
+            vec![(
+                position(0, (0, 0, 32, 16), FilterMode::BlurFill),
+                slot.clone(),
+            )],
+        );
+        slot.put(YuvFrame::new(8, 16, (180, 90, 60)));
+
+        let output = compositor.compose(0);
+        // 竖屏源居中，两侧不再是黑边
+        assert_eq!(output.y()[0], 180);
+        assert_eq!(output.u()[0], 90);
+        assert_eq!(output.y()[8 * 32 + 16], 180);
+    }
+
+    #[test]
+    fn source_region_with_zoom_and_pan() {
+        let frame = Rect::new(0, 0, 640, 480);
+        let mut tile = position(0, (0, 0, 320, 240), FilterMode::Scale);
+        assert_eq!(source_rect(&tile, frame), frame);
+
+        tile.source = Some(Region::Normalized {
+            x: 0.5,
+            y: 0.0,
+            width: 0.5,
+            height: 0.5,
+        });
+        assert_eq!(source_rect(&tile, frame), Rect::new(320, 0, 320, 240));
+
+        // 放大 2 倍后平移到右下角
+        tile.zoom = 2.0;
+        tile.pan_x = 1.0;
+        tile.pan_y = 1.0;
+        assert_eq!(source_rect(&tile, frame), Rect::new(480, 120, 160, 120));
+
+        // 超出源画面的区域被裁剪
+        tile.source = Some(Region::Pixel {
+            x: 600,
+            y: 400,
+            width: 200,
+            height: 200,
+        });
+        tile.zoom = 1.0;
+        assert_eq!(source_rect(&tile, frame), Rect::new(600, 400, 40, 80));
+    }
+
+    #[test]
+    fn set_layout_moves_tiles_at_runtime() {
+        let slot = FrameSlot::default();
+        let mut tile = position(0, (0, 0, 4, 2), FilterMode::Scale);
+        let mut compositor = compositor(canvas(8, 2), vec![(tile.clone(), slot.clone())]);
+        slot.put(YuvFrame::new(4, 2, (200, 128, 128)));
+        assert_eq!(
+            &compositor.compose(0).y()[..8],
+            &[200, 200, 200, 200, 16, 16, 16, 16]
+        );
+
+        tile.x = 4;
+        compositor.set_layout(&Layout {
+            videos: vec![tile],
+            ..Default::default()
+        });
+        assert_eq!(
+            &compositor.compose(0).y()[..8],
+            &[16, 16, 16, 16, 200, 200, 200, 200]
+        );
+    }
+
+    #[test]
+    fn anchor_overlay_inside_area() {
+        let area = Rect::new(100, 50, 400, 300);
+        assert_eq!(anchored(Anchor::TopLeft, area, 40, 20), (100, 50));
+        assert_eq!(anchored(Anchor::Center, area, 40, 20), (280, 190));
+        assert_eq!(anchored(Anchor::BottomLeft, area, 40, 20), (100, 330));
+        assert_eq!(anchored(Anchor::BottomRight, area, 40, 20), (460, 330));
+    }
+
+    #[test]
+    fn ticker_scrolls_within_area() {
+        let text = TextLayer {
+            overlay: Overlay {
+                frame: YuvFrame::new(4, 2, (200, 128, 128)),
+                alpha: AlphaMask::from_fn(4, 2, |_, _| 255),
+                x: 0,
+                y: 0,
+            },
+            scroll: Some((Rect::new(2, 0, 8, 2), 1000.0)),
+        };
+        let draw = |timestamp| {
+            let mut output = YuvFrame::new(12, 2, (16, 128, 128));
+            text.draw(&mut output, timestamp);
+            output.y()[..12].to_vec()
+        };
+        // 每毫秒 1 像素，从区域右侧进入，超出区域的部分不绘制
+        assert_eq!(draw(0), [16; 12]);
+        assert_eq!(draw(2), [16, 16, 16, 16, 16, 16, 16, 16, 200, 200, 16, 16]);
+        assert_eq!(draw(10), [16, 16, 200, 200, 16, 16, 16, 16, 16, 16, 16, 16]);
+        // 完全移出后重新开始
+        assert_eq!(draw(12), [16; 12]);
+
+        let empty = TextLayer {
+            overlay: Overlay {
+                frame: YuvFrame::new(0, 0, (200, 128, 128)),
+                alpha: AlphaMask::from_fn(0, 0, |_, _| 255),
+                x: 0,
+                y: 0,
+            },
+            scroll: Some((Rect::new(2, 0, 0, 2), 1000.0)),
+        };
+        let mut output = YuvFrame::new(12, 2, (16, 128, 128));
+        empty.draw(&mut output, 5);
+        assert_eq!(&output.y()[..12], &[16; 12]);
+    }
+
+    /// 测试用临时文件，离开作用域时删除
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let name = format!("live-merge-{}-{}", std::process::id(), name);
+            TempFile(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn image_overlay_between_video_layers() {
+        let file = TempFile::new("image_overlay_between_video_layers.png");
+        let path = file.0.clone();
+        image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 255, 255, 255]))
+            .save(&path)
+            .unwrap();
+        let bottom = FrameSlot::default();
+        let top = FrameSlot::default();
+        let mut compositor = compositor(
+            canvas(8, 2),
+            vec![
+                (position(0, (0, 0, 8, 2), FilterMode::Scale), bottom.clone()),
+                (position(2, (4, 0, 4, 2), FilterMode::Scale), top.clone()),
+            ],
+        );
+        let mut layout = Layout {
+            videos: compositor
+                .layers
+                .iter()
+                .map(|l| l.position.clone())
+                .collect(),
+            ..Default::default()
+        };
+        layout.images.push(ImageOverlay {
+            id: "logo".to_string(),
+            path: path.clone(),
+            layer: 1,
+            x: 2,
+            y: 0,
+            width: Some(4),
+            height: None,
+            opacity: 1.0,
+        });
+        compositor.set_layout(&layout);
+        for image in &mut compositor.images {
+            image.poll(true);
+        }
+        bottom.put(YuvFrame::new(8, 2, (100, 128, 128)));
+        top.put(YuvFrame::new(4, 2, (50, 128, 128)));
+
+        // 图片在下层视频之上、上层视频之下
+        let output = compositor.compose(0);
+        assert_eq!(&output.y()[..8], &[100, 100, 235, 235, 50, 50, 50, 50]);
+
+        // 超过画布的图片不加载
+        layout.images[0].width = Some(16);
+        compositor.set_layout(&layout);
+        compositor.images[0].poll(true);
+        assert!(compositor.images[0].overlay.is_none());
+    }
+
+    #[test]
+    fn template_follows_active_sources() {
+        let left = FrameSlot::default();
+        let right = FrameSlot::default();
+        let mut compositor = Compositor::new(canvas(8, 2), FilterRegistry::default());
+        compositor.add_source("a", left.clone());
+        compositor.add_source("b", right.clone());
+        let mut template = Template::new(TemplateKind::SideBySide);
+        template.mode = FilterMode::Scale;
+        template.leave_secs = 0.05;
+        compositor.set_layout(&Layout {
+            template: Some(template),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        // 没有活跃的源时只有背景
+        assert_eq!(&compositor.compose_at(0, start).y()[..8], &[16; 8]);
+
+        left.put_at(YuvFrame::new(2, 2, (100, 128, 128)), start);
+        right.put_at(YuvFrame::new(2, 2, (200, 128, 128)), start);
+        assert_eq!(
+            &compositor.compose_at(0, start).y()[..8],
+            &[100, 100, 100, 100, 200, 200, 200, 200]
+        );
+
+        // b 离开后 a 铺满画布
+        let now = start + Duration::from_millis(60);
+        left.put_at(YuvFrame::new(2, 2, (100, 128, 128)), now);
+        assert_eq!(&compositor.compose_at(0, now).y()[..8], &[100; 8]);
+    }
+
+    #[test]
+    fn follow_and_highlight_speaker() {
+        let a = FrameSlot::default();
+        let b = FrameSlot::default();
+        let mut compositor = Compositor::new(canvas(16, 8), FilterRegistry::default());
+        compositor.add_source("a", a.clone());
+        compositor.add_source("b", b.clone());
+        let detector = SpeakerDetector::new(Default::default());
+        compositor.set_speaker_detector(detector.clone());
+        let mut template = Template::new(TemplateKind::PictureInPicture);
+        template.mode = FilterMode::Scale;
+        template.follow_speaker = true;
+        compositor.set_layout(&Layout {
+            template: Some(template),
+            speaker_border: Some(Border {
+                color: Color::white(),
+                width: 2,
+            }),
+            ..Default::default()
+        });
+        a.put(YuvFrame::new(2, 2, (100, 128, 128)));
+        b.put(YuvFrame::new(2, 2, (50, 128, 128)));
+        // 没有发言人时 a 为主画面
+        assert_eq!(compositor.compose(0).y()[0], 100);
+
+        let start = Instant::now();
+        for t in (0..500).step_by(20) {
+            detector.update("b", 10, start - Duration::from_millis(500 - t));
+        }
+        // b 成为主画面并带边框
+        let output = compositor.compose(0);
+        assert_eq!(output.y()[0], 235);
+        assert_eq!(output.y()[3 * 16 + 3], 50);
+    }
+
+    #[test]
+    fn transition_tweens_position_and_fades() {
+        let a = FrameSlot::default();
+        let b = FrameSlot::default();
+        let mut tile = position(0, (0, 0, 4, 2), FilterMode::Scale);
+        tile.id = "a".to_string();
+        let mut compositor = compositor(canvas(12, 2), vec![(tile.clone(), a.clone())]);
+        compositor.add_source("b", b.clone());
+        a.put(YuvFrame::new(4, 2, (200, 128, 128)));
+        b.put(YuvFrame::new(4, 2, (100, 128, 128)));
+        assert_eq!(&compositor.compose(0).y()[..4], &[200; 4]);
+
+        // a 右移 8 像素，b 淡入
+        tile.x = 8;
+        let mut added = position(1, (0, 0, 4, 2), FilterMode::Scale);
+        added.id = "b".to_string();
+        compositor.set_layout(&Layout {
+            videos: vec![tile, added],
+            transition: Transition {
+                duration_secs: 1.0,
+                easing: Easing::Linear,
+            },
+            ..Default::default()
+        });
+        assert_eq!(
+            &compositor.compose(1000).y()[..12],
+            &[200, 200, 200, 200, 16, 16, 16, 16, 16, 16, 16, 16]
+        );
+        let y = compositor.compose(1500).y()[..12].to_vec();
+        assert_eq!(&y[4..8], &[200; 4]);
+        assert_eq!(y[0], 58);
+        let y = compositor.compose(2000).y()[..12].to_vec();
+        assert_eq!(y, [100, 100, 100, 100, 16, 16, 16, 16, 200, 200, 200, 200]);
+    }
+
+    #[test]
+    fn chroma_key_reveals_lower_layer() {
+        let bottom = FrameSlot::default();
+        let top = FrameSlot::default();
+        let mut keyed = position(1, (0, 0, 8, 2), FilterMode::Scale);
+        keyed.chroma_key = Some(Default::default());
+        let mut compositor = compositor(
+            canvas(8, 2),
+            vec![
+                (position(0, (0, 0, 8, 2), FilterMode::Scale), bottom.clone()),
+                (keyed, top.clone()),
+            ],
+        );
+        bottom.put(YuvFrame::new(8, 2, (100, 128, 128)));
+        // 左半为绿幕，右半为肤色人物及灰色背景
+        let mut rgb = Vec::new();
+        for _ in 0..2 {
+            for x in 0..8 {
+                rgb.extend_from_slice(match x {
+                    0..=3 => &[0, 255, 0],
+                    4 | 5 => &[220, 160, 120],
+                    _ => &[128, 128, 128],
+                });
+            }
+        }
+        top.put(YuvFrame::from_rgb(8, 2, &rgb));
+
+        let output = compositor.compose(0);
+        assert_eq!(&output.y()[..4], &[100; 4]);
+        assert_eq!(output.u()[0], 128);
+        let (y, u, _) = rgb_to_yuv(220, 160, 120);
+        assert_eq!((output.y()[4], output.u()[2]), (y, u));
+        // 中性色完全不透明
+        let (y, u, _) = rgb_to_yuv(128, 128, 128);
+        assert_eq!((output.y()[6], output.u()[3]), (y, u));
+    }
+
+    #[test]
+    fn rotate_and_flip_source() {
+        let slot = FrameSlot::default();
+        let mut tile = position(0, (0, 0, 2, 4), FilterMode::Fit);
+        tile.rotation = Rotation::Clockwise90;
+        tile.flip_vertical = true;
+        let mut compositor = compositor(canvas(2, 4), vec![(tile, slot.clone())]);
+        // 横屏画面左暗右亮，旋转后填满竖屏 tile，左列转到顶部后再被翻到底部
+        let mut frame = YuvFrame::new(4, 2, (50, 128, 128));
+        frame.draw_scaled(
+            &YuvFrame::new(2, 2, (200, 128, 128)),
+            Rect::new(0, 0, 2, 2),
+            Rect::new(2, 0, 2, 2),
+        );
+        slot.put(frame);
+
+        let output = compositor.compose(0);
+        assert_eq!(output.y(), &[200, 200, 200, 200, 50, 50, 50, 50]);
+    }
+
+    #[test]
+    fn filter_chain_sees_output_timestamp() {
+        struct Stamp;
+        impl VideoFilter for Stamp {
+            fn apply(&mut self, frame: &mut YuvFrame, timestamp: u32) -> anyhow::Result<()> {
+                let (width, height) = frame.dimensions();
+                *frame = YuvFrame::new(width, height, (timestamp as u8, 128, 128));
+                Ok(())
+            }
+        }
+        let mut filters = FilterRegistry::default();
+        filters.register("stamp", |_| Ok(Box::new(Stamp) as Box<dyn VideoFilter>));
+
+        let slot = FrameSlot::default();
+        let mut tile = position(0, (0, 0, 2, 2), FilterMode::Scale);
+        for name in ["missing", "stamp"] {
+            tile.filters.push(FilterConfig {
+                name: name.to_string(),
+                config: Default::default(),
+            });
+        }
+        let mut compositor = Compositor::new(canvas(2, 2), filters);
+        compositor.add_source("layer0", slot.clone());
+        compositor.set_layout(&Layout {
+            videos: vec![tile],
+            ..Default::default()
+        });
+        slot.put(YuvFrame::new(2, 2, (16, 128, 128)));
+
+        // 源帧不变时滤镜仍按每次输出的时间戳重新处理
+        assert_eq!(compositor.compose(40).y()[0], 40);
+        assert_eq!(compositor.compose(80).y()[0], 80);
+    }
+
+    #[test]
+    fn reuse_filters_with_unchanged_config() {
+        /// 输出已处理的帧数
+        struct Count(u8);
+        impl VideoFilter for Count {
+            fn apply(&mut self, frame: &mut YuvFrame, _timestamp: u32) -> anyhow::Result<()> {
+                self.0 += 1;
+                let (width, height) = frame.dimensions();
+                *frame = YuvFrame::new(width, height, (self.0, 128, 128));
+                Ok(())
+            }
+        }
+        let mut filters = FilterRegistry::default();
+        filters.register("count", |_| Ok(Box::new(Count(0)) as Box<dyn VideoFilter>));
+
+        let slot = FrameSlot::default();
+        let mut tile = position(0, (0, 0, 2, 2), FilterMode::Scale);
+        tile.filters.push(FilterConfig {
+            name: "count".to_string(),
+            config: Default::default(),
+        });
+        let mut compositor = Compositor::new(canvas(4, 2), filters);
+        compositor.add_source("layer0", slot.clone());
+        let mut layout = Layout {
+            videos: vec![tile],
+            ..Default::default()
+        };
+        compositor.set_layout(&layout);
+        slot.put(YuvFrame::new(2, 2, (16, 128, 128)));
+        assert_eq!(compositor.compose(0).y()[0], 1);
+
+        // 只移动 tile 时保留滤镜状态
+        layout.videos[0].x = 2;
+        compositor.set_layout(&layout);
+        assert_eq!(compositor.compose(40).y()[2], 2);
+
+        // 滤镜配置变化时重新创建
+        layout.videos[0].filters[0].config = serde_json::json!({ "step": 1 });
+        compositor.set_layout(&layout);
+        assert_eq!(compositor.compose(80).y()[2], 1);
+    }
+}
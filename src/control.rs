@@ -0,0 +1,54 @@
+use crate::filter::FilterRegistry;
+use crate::param::{Canvas, EncoderSettings, Layout};
+use crate::speaker::SpeakerDetector;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// 运行时可修改的任务参数，由控制接口持有
+#[derive(Clone)]
+pub struct JobControl {
+    /// 输出画布，只读，用于检查布局
+    pub canvas: Canvas,
+
+    /// 布局中 tile 可引用的滤镜，用于检查滤镜配置
+    pub filters: FilterRegistry,
+
+    /// 编码参数，修改后编码线程重建编码器
+    pub encoder: Arc<watch::Sender<EncoderSettings>>,
+
+    /// 画面布局，修改后从下一帧开始生效
+    pub layout: Arc<watch::Sender<Layout>>,
+
+    /// 发言人检测状态，只读
+    pub speaker: SpeakerDetector,
+}
+
+/// 编码线程持有的参数接收端
+pub struct JobUpdates {
+    pub encoder: watch::Receiver<EncoderSettings>,
+    pub layout: watch::Receiver<Layout>,
+}
+
+pub fn channel(
+    canvas: Canvas,
+    filters: FilterRegistry,
+    encoder: EncoderSettings,
+    layout: Layout,
+    speaker: SpeakerDetector,
+) -> (JobControl, JobUpdates) {
+    let (encoder_sender, encoder_receiver) = watch::channel(encoder);
+    let (layout_sender, layout_receiver) = watch::channel(layout);
+    (
+        JobControl {
+            canvas,
+            filters,
+            encoder: Arc::new(encoder_sender),
+            layout: Arc::new(layout_sender),
+            speaker,
+        },
+        JobUpdates {
+            encoder: encoder_receiver,
+            layout: layout_receiver,
+        },
+    )
+}
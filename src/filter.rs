@@ -0,0 +1,192 @@
+use crate::param::{ColorAdjustment, FilterConfig};
+use crate::yuv::YuvFrame;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// 单路源的视频滤镜，在合成前处理源帧
+///
+/// 滤镜实例由 [`FilterRegistry`] 按 tile 配置中的参数创建，每个 tile 独立持有，可保存跨帧状态
+pub trait VideoFilter: Send {
+    /// 处理一帧，timestamp 为输出时间戳（毫秒）
+    fn apply(&mut self, frame: &mut YuvFrame, timestamp: u32) -> anyhow::Result<()>;
+}
+
+type Factory = dyn Fn(&serde_json::Value) -> anyhow::Result<Box<dyn VideoFilter>> + Send + Sync;
+
+/// 按名称注册的滤镜，tile 的滤镜链通过名称引用
+#[derive(Clone)]
+pub struct FilterRegistry {
+    factories: HashMap<String, Arc<Factory>>,
+}
+
+impl FilterRegistry {
+    /// 不含内置滤镜的空注册表
+    pub fn empty() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// 注册滤镜，同名滤镜被替换
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        factory: impl Fn(&serde_json::Value) -> anyhow::Result<Box<dyn VideoFilter>>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        self.factories.insert(name.into(), Arc::new(factory));
+    }
+
+    /// 注册参数可反序列化为 C 的滤镜，build 可检查参数并返回错误
+    pub fn register_with<C, F>(
+        &mut self,
+        name: impl Into<String>,
+        build: impl Fn(C) -> anyhow::Result<F> + Send + Sync + 'static,
+    ) where
+        C: DeserializeOwned,
+        F: VideoFilter + 'static,
+    {
+        self.register(name, move |config| {
+            // 未设置参数时按空对象解析，使用参数的默认值
+            let config = match config {
+                serde_json::Value::Null => serde_json::from_value(serde_json::json!({}))?,
+                config => serde_json::from_value(config.clone())?,
+            };
+            Ok(Box::new(build(config)?) as Box<dyn VideoFilter>)
+        });
+    }
+
+    /// 按配置创建滤镜
+    pub fn build(&self, config: &FilterConfig) -> anyhow::Result<Box<dyn VideoFilter>> {
+        let factory = self
+            .factories
+            .get(&config.name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown filter: {}", config.name))?;
+        factory(&config.config)
+            .map_err(|e| anyhow::anyhow!("Invalid config of filter {}: {}", config.name, e))
+    }
+}
+
+/// 含内置的 blur 及 color 滤镜
+impl Default for FilterRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register_with("blur", |config: BlurConfig| {
+            // 模糊的耗时与半径成正比
+            anyhow::ensure!(
+                config.radius <= MAX_BLUR_RADIUS,
+                "Blur radius must not exceed {}: {}",
+                MAX_BLUR_RADIUS,
+                config.radius
+            );
+            Ok(Blur(config.radius))
+        });
+        registry.register_with("color", |adjustment: ColorAdjustment| {
+            adjustment.validate()?;
+            Ok(Color(adjustment))
+        });
+        registry
+    }
+}
+
+/// blur 滤镜的半径上限
+const MAX_BLUR_RADIUS: usize = 64;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct BlurConfig {
+    radius: usize,
+}
+
+impl Default for BlurConfig {
+    fn default() -> Self {
+        Self { radius: 4 }
+    }
+}
+
+/// 盒式模糊
+struct Blur(usize);
+
+impl VideoFilter for Blur {
+    fn apply(&mut self, frame: &mut YuvFrame, _timestamp: u32) -> anyhow::Result<()> {
+        frame.blur(self.0);
+        Ok(())
+    }
+}
+
+/// 亮度、对比度、饱和度及 gamma 调整
+struct Color(ColorAdjustment);
+
+impl VideoFilter for Color {
+    fn apply(&mut self, frame: &mut YuvFrame, _timestamp: u32) -> anyhow::Result<()> {
+        frame.adjust_color(&self.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openh264::formats::YUVSource;
+
+    /// 反相纯色帧的亮度
+    struct Invert;
+
+    impl VideoFilter for Invert {
+        fn apply(&mut self, frame: &mut YuvFrame, _timestamp: u32) -> anyhow::Result<()> {
+            let (width, height) = frame.dimensions();
+            let y = 255 - frame.y()[0];
+            *frame = YuvFrame::new(width, height, (y, 128, 128));
+            Ok(())
+        }
+    }
+
+    fn config(name: &str, config: serde_json::Value) -> FilterConfig {
+        FilterConfig {
+            name: name.to_string(),
+            config,
+        }
+    }
+
+    #[test]
+    fn build_registered_filters() {
+        let mut registry = FilterRegistry::default();
+        registry.register("invert", |_| Ok(Box::new(Invert) as Box<dyn VideoFilter>));
+
+        let mut frame = YuvFrame::new(2, 2, (100, 128, 128));
+        let mut invert = registry
+            .build(&config("invert", Default::default()))
+            .unwrap();
+        invert.apply(&mut frame, 0).unwrap();
+        assert_eq!(frame.y()[0], 155);
+        invert.apply(&mut frame, 40).unwrap();
+        assert_eq!(frame.y()[0], 100);
+
+        let mut color = registry
+            .build(&config("color", serde_json::json!({ "saturation": 0.0 })))
+            .unwrap();
+        let mut frame = YuvFrame::new(2, 2, (100, 200, 50));
+        color.apply(&mut frame, 0).unwrap();
+        assert_eq!((frame.u()[0], frame.v()[0]), (128, 128));
+
+        assert!(registry
+            .build(&config("missing", Default::default()))
+            .is_err());
+        assert!(registry
+            .build(&config("blur", serde_json::json!({ "radius": "big" })))
+            .is_err());
+        assert!(registry
+            .build(&config("blur", serde_json::json!({ "radius": 1_000_000 })))
+            .is_err());
+        assert!(registry
+            .build(&config("blur", serde_json::json!({ "radius": 64 })))
+            .is_ok());
+        assert!(registry
+            .build(&config("color", serde_json::json!({ "gamma": -1.0 })))
+            .is_err());
+    }
+}
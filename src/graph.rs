@@ -0,0 +1,566 @@
+#![cfg_attr(not(feature = "ffmpeg"), allow(dead_code))]
+use crate::compositor::{placement, source_rect};
+use crate::param::{Canvas, Color, FilterMode, Layout, Rotation, VideoPosition};
+use crate::template;
+use crate::yuv::Rect;
+
+#[cfg(feature = "ffmpeg")]
+pub use backend::GraphCompositor;
+
+/// 滤镜图的一路视频输入，构建时对应名为 `in{序号}` 的 buffer 源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphInput {
+    pub id: String,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// 按布局生成 ffmpeg 滤镜图描述
+///
+/// 视频输入的标签为 `[in0]`、`[in1]`…，合成结果输出到 `[out]`。滤镜图只合成视频；图片、文字叠加、
+/// 形状遮罩、过渡动画及自定义滤镜只有 [`crate::compositor::Compositor`] 支持
+///
+/// 音频以 Opus RTP 包直接转发、不经解码，因此没有 amix，待实现 Opus 解码及编码后再加入
+pub fn describe(canvas: &Canvas, layout: &Layout, inputs: &[GraphInput]) -> String {
+    let mut videos = match &layout.template {
+        Some(template) => {
+            let ids: Vec<String> = inputs.iter().map(|input| input.id.clone()).collect();
+            template::generate(template, canvas, &ids)
+        }
+        None => Vec::new(),
+    };
+    videos.extend(layout.videos.iter().cloned());
+    videos.sort_by_key(|video| video.layer);
+    let tiles: Vec<(usize, &VideoPosition)> = videos
+        .iter()
+        .filter_map(|video| {
+            let index = inputs.iter().position(|input| input.id == video.id)?;
+            Some((index, video))
+        })
+        .collect();
+
+    let mut chains = vec![format!(
+        "color=c={}:s={}x{}:r={},format=yuv420p[bg]",
+        hex(canvas.background),
+        canvas.width,
+        canvas.height,
+        canvas.fps
+    )];
+    // 同一源用于多个 tile 时先复制，未使用的源接到 nullsink
+    let mut labels: Vec<Vec<String>> = Vec::new();
+    for index in 0..inputs.len() {
+        let count = tiles.iter().filter(|(i, _)| *i == index).count();
+        let split: Vec<String> = match count {
+            0 => {
+                chains.push(format!("[in{}]nullsink", index));
+                Vec::new()
+            }
+            1 => vec![format!("in{}", index)],
+            _ => {
+                let split: Vec<String> = (0..count).map(|j| format!("in{}_{}", index, j)).collect();
+                chains.push(format!("[in{}]split={}{}", index, count, brackets(&split)));
+                split
+            }
+        };
+        // 按 tile 顺序取用
+        labels.push(split.into_iter().rev().collect());
+    }
+
+    let mut base = "bg".to_string();
+    for (n, (index, position)) in tiles.iter().enumerate() {
+        let label = labels[*index].pop().unwrap();
+        let input = &inputs[*index];
+        base = overlay_tile(&mut chains, n, &label, input, position, &base);
+    }
+    chains.push(format!("[{}]format=yuv420p[out]", base));
+    chains.join(";")
+}
+
+/// 处理一个 tile 并叠加到 base 上，返回叠加结果的标签
+fn overlay_tile(
+    chains: &mut Vec<String>,
+    n: usize,
+    label: &str,
+    input: &GraphInput,
+    position: &VideoPosition,
+    base: &str,
+) -> String {
+    if position.mask.is_some() || !position.filters.is_empty() {
+        log::warn!(
+            "Filter graph ignores mask and filters of tile {}",
+            position.id
+        );
+    }
+    let mut filters = Vec::new();
+    let (mut width, mut height) = (input.width, input.height);
+    match position.rotation {
+        Rotation::None => {}
+        Rotation::Clockwise90 => filters.push("transpose=clock".to_string()),
+        Rotation::Clockwise180 => filters.push("hflip,vflip".to_string()),
+        Rotation::Clockwise270 => filters.push("transpose=cclock".to_string()),
+    }
+    if matches!(
+        position.rotation,
+        Rotation::Clockwise90 | Rotation::Clockwise270
+    ) {
+        (width, height) = (height, width);
+    }
+    if position.flip_horizontal {
+        filters.push("hflip".to_string());
+    }
+    if position.flip_vertical {
+        filters.push("vflip".to_string());
+    }
+    // 与 Compositor 一致，先抠像后调色
+    let mut alpha = false;
+    if let Some(key) = &position.chroma_key {
+        filters.push(format!(
+            "format=yuva420p,chromakey=color={}:similarity={}:blend={}",
+            hex(key.color),
+            key.similarity.clamp(0.01, 1.0),
+            key.smoothness.clamp(0.0, 1.0)
+        ));
+        alpha = true;
+    }
+    let eq = position
+        .color_adjustment
+        .filter(|adjustment| !adjustment.is_identity())
+        .map(|adjustment| {
+            format!(
+                "eq=brightness={}:contrast={}:saturation={}:gamma={}",
+                adjustment.brightness, adjustment.contrast, adjustment.saturation, adjustment.gamma
+            )
+        });
+
+    let target = Rect::new(
+        position.x as isize,
+        position.y as isize,
+        position.width as usize,
+        position.height as usize,
+    );
+    let source = source_rect(position, Rect::new(0, 0, width, height));
+    let (src, dst) = placement(position.mode, source, target);
+    if src.is_empty() || dst.is_empty() {
+        chains.push(format!("[{}]nullsink", label));
+        return base.to_string();
+    }
+    let opacity = position.opacity.clamp(0.0, 1.0);
+    let mut base = base.to_string();
+    let mut processed = label.to_string();
+    match eq {
+        // eq 不支持带透明度的格式，抠像后拆出透明度通道，调色后再合并
+        Some(eq) if alpha => {
+            processed = format!("t{}", n);
+            let (keyed, matte) = (format!("t{}k", n), format!("t{}m", n));
+            let (adjusted, extracted) = (format!("t{}c", n), format!("t{}a", n));
+            chains.push(format!(
+                "[{}]{},split[{}][{}]",
+                label,
+                filters.join(","),
+                keyed,
+                matte
+            ));
+            chains.push(format!("[{}]alphaextract[{}]", matte, extracted));
+            chains.push(format!("[{}]format=yuv420p,{}[{}]", keyed, eq, adjusted));
+            chains.push(format!(
+                "[{}][{}]alphamerge[{}]",
+                adjusted, extracted, processed
+            ));
+        }
+        eq => {
+            filters.extend(eq);
+            if !filters.is_empty() {
+                processed = format!("t{}", n);
+                chains.push(format!("[{}]{}[{}]", label, filters.join(","), processed));
+            }
+        }
+    }
+
+    // BlurFill 的背景按 Crop 铺满 tile 后模糊
+    let mut tile_filters = Vec::new();
+    if position.mode == FilterMode::BlurFill {
+        let (background_src, _) = placement(FilterMode::Crop, source, target);
+        let (foreground, background) = (format!("t{}f", n), format!("t{}b", n));
+        chains.push(format!(
+            "[{}]split[{}][{}]",
+            processed, foreground, background
+        ));
+        let mut background_filters = vec![
+            crop(background_src),
+            format!("scale={}:{}", target.width, target.height),
+            "boxblur=luma_radius=10:luma_power=2".to_string(),
+        ];
+        fade(&mut background_filters, opacity, alpha);
+        let blurred = format!("t{}s", n);
+        chains.push(format!(
+            "[{}]{}[{}]",
+            background,
+            background_filters.join(","),
+            blurred
+        ));
+        let filled = format!("b{}", n);
+        chains.push(format!(
+            "[{}][{}]overlay=x={}:y={}:eof_action=repeat[{}]",
+            base, blurred, target.x, target.y, filled
+        ));
+        base = filled;
+        processed = foreground;
+    }
+    tile_filters.push(crop(src));
+    tile_filters.push(format!("scale={}:{}", dst.width, dst.height));
+    fade(&mut tile_filters, opacity, alpha);
+    let tile = format!("v{}", n);
+    let output = format!("o{}", n);
+    chains.push(format!(
+        "[{}]{}[{}]",
+        processed,
+        tile_filters.join(","),
+        tile
+    ));
+    chains.push(format!(
+        "[{}][{}]overlay=x={}:y={}:eof_action=repeat[{}]",
+        base, tile, dst.x, dst.y, output
+    ));
+    output
+}
+
+fn crop(rect: Rect) -> String {
+    format!("crop={}:{}:{}:{}", rect.width, rect.height, rect.x, rect.y)
+}
+
+/// 不透明度小于 1 时按比例降低透明度通道
+fn fade(filters: &mut Vec<String>, opacity: f32, alpha: bool) {
+    if opacity >= 1.0 {
+        return;
+    }
+    if !alpha {
+        filters.push("format=yuva420p".to_string());
+    }
+    filters.push(format!("lutyuv=a=val*{}", opacity));
+}
+
+fn hex(color: Color) -> String {
+    format!("0x{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn brackets(labels: &[String]) -> String {
+    labels.iter().map(|label| format!("[{}]", label)).collect()
+}
+
+/// 基于 ffmpeg 滤镜图的合成后端，与 [`crate::compositor::Compositor`] 二选一
+#[cfg(feature = "ffmpeg")]
+mod backend {
+    use super::{describe, GraphInput};
+    use crate::compositor::{Compose, FrameSlot};
+    use crate::param::{Canvas, Layout};
+    use crate::yuv::YuvFrame;
+    use ffmpeg::filter::Graph;
+    use ffmpeg::format::Pixel;
+    use ffmpeg::frame::Video;
+    use ffmpeg_sys_next::{av_strdup, avfilter_graph_parse_ptr, avfilter_inout_alloc};
+    use ffmpeg_sys_next::{avfilter_inout_free, AVFilterInOut};
+    use openh264::formats::YUVSource;
+    use std::ffi::CString;
+    use std::ptr;
+    use std::sync::Arc;
+
+    pub struct GraphCompositor {
+        canvas: Canvas,
+        layout: Layout,
+        sources: Vec<(String, FrameSlot)>,
+        /// 当前滤镜图、构建时各路输入的尺寸及输出时间戳，布局或源尺寸变化时重建
+        ///
+        /// 重建后 color 源的 pts 从 0 开始，送入的帧以构建时的时间戳为 0
+        graph: Option<(Graph, Vec<(usize, usize)>, u32)>,
+        /// 源尚未出帧时送入的黑帧
+        placeholder: Arc<YuvFrame>,
+        output: YuvFrame,
+    }
+
+    impl GraphCompositor {
+        pub fn new(canvas: Canvas) -> anyhow::Result<Self> {
+            ffmpeg::init()?;
+            let (width, height) = (canvas.width as usize, canvas.height as usize);
+            let background = canvas.background.to_yuv();
+            Ok(Self {
+                canvas,
+                layout: Layout::default(),
+                sources: Vec::new(),
+                graph: None,
+                placeholder: Arc::new(YuvFrame::new(width, height, (16, 128, 128))),
+                output: YuvFrame::new(width, height, background),
+            })
+        }
+
+        /// 注册一路源，布局中 id 相同的 tile 显示该源
+        pub fn add_source(&mut self, id: impl Into<String>, slot: FrameSlot) {
+            self.sources.push((id.into(), slot));
+            self.graph = None;
+        }
+
+        fn build(&self, sizes: &[(usize, usize)]) -> anyhow::Result<Graph> {
+            let inputs: Vec<GraphInput> = self
+                .sources
+                .iter()
+                .zip(sizes)
+                .map(|((id, _), &(width, height))| GraphInput {
+                    id: id.clone(),
+                    width,
+                    height,
+                })
+                .collect();
+            let spec = describe(&self.canvas, &self.layout, &inputs);
+            log::info!("Build filter graph: {}", spec);
+
+            let mut graph = Graph::new();
+            let buffer = find_filter("buffer")?;
+            for (index, input) in inputs.iter().enumerate() {
+                let args = format!(
+                    "video_size={}x{}:pix_fmt=yuv420p:time_base=1/1000:pixel_aspect=1/1",
+                    input.width, input.height
+                );
+                graph.add(&buffer, &format!("in{}", index), &args)?;
+            }
+            graph.add(&find_filter("buffersink")?, "out", "")?;
+            parse(&mut graph, &spec, inputs.len())?;
+            graph.validate()?;
+            Ok(graph)
+        }
+
+        /// 送入各路源的最新帧并取出合成结果，滤镜图尚未出帧时保留上一帧
+        fn run(&mut self, timestamp: u32) -> anyhow::Result<()> {
+            let frames: Vec<Arc<YuvFrame>> = self
+                .sources
+                .iter()
+                .map(|(_, slot)| match slot.latest() {
+                    Some((frame, _)) => frame,
+                    None => self.placeholder.clone(),
+                })
+                .collect();
+            let sizes: Vec<(usize, usize)> =
+                frames.iter().map(|frame| frame.dimensions()).collect();
+            if !matches!(&self.graph, Some((_, built, _)) if *built == sizes) {
+                self.graph = Some((self.build(&sizes)?, sizes, timestamp));
+            }
+            let (graph, _, start) = self.graph.as_mut().unwrap();
+            let pts = timestamp.wrapping_sub(*start) as i64;
+
+            for (index, frame) in frames.iter().enumerate() {
+                let mut video = to_video(frame);
+                video.set_pts(Some(pts));
+                graph
+                    .get(&format!("in{}", index))
+                    .ok_or_else(|| anyhow::anyhow!("Filter in{} not found", index))?
+                    .source()
+                    .add(&video)?;
+            }
+            let mut sink = graph
+                .get("out")
+                .ok_or_else(|| anyhow::anyhow!("Filter out not found"))?;
+            let mut video = Video::empty();
+            loop {
+                match sink.sink().frame(&mut video) {
+                    Ok(()) => self.output = YuvFrame::copy_from(&VideoPlanes(&video)),
+                    Err(ffmpeg::Error::Other {
+                        errno: ffmpeg::error::EAGAIN,
+                    }) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Compose for GraphCompositor {
+        fn canvas(&self) -> &Canvas {
+            &self.canvas
+        }
+
+        fn set_layout(&mut self, layout: &Layout) {
+            self.layout = layout.clone();
+            self.graph = None;
+        }
+
+        fn compose(&mut self, timestamp: u32) -> &YuvFrame {
+            if let Err(e) = self.run(timestamp) {
+                log::error!("Filter graph error: {}", e);
+                self.graph = None;
+            }
+            &self.output
+        }
+    }
+
+    fn find_filter(name: &str) -> anyhow::Result<ffmpeg::Filter> {
+        ffmpeg::filter::find(name).ok_or_else(|| anyhow::anyhow!("Filter '{}' not found", name))
+    }
+
+    /// 将 buffer 源 in0、in1… 及 buffersink out 接到描述中同名的标签上
+    ///
+    /// ffmpeg-next 的 Parser 只能串接两个端点，这里直接构造 AVFilterInOut 链表
+    fn parse(graph: &mut Graph, spec: &str, inputs: usize) -> anyhow::Result<()> {
+        let spec = CString::new(spec)?;
+        unsafe {
+            let mut outputs: *mut AVFilterInOut = ptr::null_mut();
+            for index in (0..inputs).rev() {
+                outputs = endpoint(graph, &format!("in{}", index), outputs)?;
+            }
+            let mut sink = endpoint(graph, "out", ptr::null_mut())?;
+            let result = avfilter_graph_parse_ptr(
+                graph.as_mut_ptr(),
+                spec.as_ptr(),
+                &mut sink,
+                &mut outputs,
+                ptr::null_mut(),
+            );
+            avfilter_inout_free(&mut sink);
+            avfilter_inout_free(&mut outputs);
+            if result < 0 {
+                anyhow::bail!(
+                    "Failed to parse filter graph: {}",
+                    ffmpeg::Error::from(result)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// 滤镜图的一个已有端点，next 为链表中的下一个
+    unsafe fn endpoint(
+        graph: &mut Graph,
+        name: &str,
+        next: *mut AVFilterInOut,
+    ) -> anyhow::Result<*mut AVFilterInOut> {
+        let mut context = graph
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Filter {} not found", name))?;
+        let endpoint = avfilter_inout_alloc();
+        anyhow::ensure!(!endpoint.is_null(), "Out of memory");
+        let name = CString::new(name)?;
+        (*endpoint).name = av_strdup(name.as_ptr());
+        (*endpoint).filter_ctx = context.as_mut_ptr();
+        (*endpoint).pad_idx = 0;
+        (*endpoint).next = next;
+        Ok(endpoint)
+    }
+
+    fn to_video(frame: &YuvFrame) -> Video {
+        let (width, height) = frame.dimensions();
+        let mut video = Video::new(Pixel::YUV420P, width as u32, height as u32);
+        for (index, plane) in [frame.y(), frame.u(), frame.v()].into_iter().enumerate() {
+            let plane_width = video.plane_width(index) as usize;
+            let stride = video.stride(index);
+            let data = video.data_mut(index);
+            for (row, line) in plane.chunks(plane_width).enumerate() {
+                data[row * stride..row * stride + plane_width].copy_from_slice(line);
+            }
+        }
+        video
+    }
+
+    /// 以 YUVSource 读取 ffmpeg 帧，复用 [`YuvFrame::copy_from`]
+    struct VideoPlanes<'a>(&'a Video);
+
+    impl YUVSource for VideoPlanes<'_> {
+        fn width(&self) -> i32 {
+            self.0.width() as i32
+        }
+
+        fn height(&self) -> i32 {
+            self.0.height() as i32
+        }
+
+        fn y(&self) -> &[u8] {
+            self.0.data(0)
+        }
+
+        fn u(&self) -> &[u8] {
+            self.0.data(1)
+        }
+
+        fn v(&self) -> &[u8] {
+            self.0.data(2)
+        }
+
+        fn y_stride(&self) -> i32 {
+            self.0.stride(0) as i32
+        }
+
+        fn u_stride(&self) -> i32 {
+            self.0.stride(1) as i32
+        }
+
+        fn v_stride(&self) -> i32 {
+            self.0.stride(2) as i32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::{ChromaKey, ColorAdjustment};
+
+    fn canvas() -> Canvas {
+        Canvas {
+            width: 1280,
+            height: 720,
+            fps: 25,
+            background: Default::default(),
+        }
+    }
+
+    fn input(id: &str, width: usize, height: usize) -> GraphInput {
+        GraphInput {
+            id: id.to_string(),
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn describe_tiles() {
+        let mut main = VideoPosition::new("a", 0, 0, 1280, 720);
+        main.mode = FilterMode::Crop;
+        let mut pip = VideoPosition::new("a", 960, 540, 320, 180);
+        pip.layer = 1;
+        pip.opacity = 0.5;
+        pip.rotation = Rotation::Clockwise90;
+        let mut keyed = VideoPosition::new("b", 0, 0, 640, 360);
+        keyed.layer = 2;
+        keyed.mode = FilterMode::Scale;
+        keyed.chroma_key = Some(ChromaKey::default());
+        keyed.color_adjustment = Some(ColorAdjustment {
+            brightness: 0.1,
+            saturation: 1.2,
+            ..Default::default()
+        });
+        let layout = Layout {
+            videos: vec![keyed, pip, main],
+            ..Default::default()
+        };
+        let inputs = [input("a", 640, 360), input("b", 320, 240), input("c", 2, 2)];
+
+        let spec = describe(&canvas(), &layout, &inputs);
+        let chains: Vec<&str> = spec.split(';').collect();
+        assert_eq!(
+            chains,
+            vec![
+                "color=c=0x000000:s=1280x720:r=25,format=yuv420p[bg]",
+                "[in0]split=2[in0_0][in0_1]",
+                "[in2]nullsink",
+                "[in0_0]crop=640:360:0:0,scale=1280:720[v0]",
+                "[bg][v0]overlay=x=0:y=0:eof_action=repeat[o0]",
+                "[in0_1]transpose=clock[t1]",
+                "[t1]crop=360:640:0:0,scale=101:180,format=yuva420p,lutyuv=a=val*0.5[v1]",
+                "[o0][v1]overlay=x=1069:y=540:eof_action=repeat[o1]",
+                "[in1]format=yuva420p,chromakey=color=0x00ff00:similarity=0.4:blend=0.08,split[t2k][t2m]",
+                "[t2m]alphaextract[t2a]",
+                "[t2k]format=yuv420p,eq=brightness=0.1:contrast=1:saturation=1.2:gamma=1[t2c]",
+                "[t2c][t2a]alphamerge[t2]",
+                "[t2]crop=320:240:0:0,scale=640:360[v2]",
+                "[o1][v2]overlay=x=0:y=0:eof_action=repeat[o2]",
+                "[o2]format=yuv420p[out]",
+            ]
+        );
+    }
+}
@@ -1,23 +1,49 @@
 #![allow(dead_code)]
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fmt;
 
 pub enum H264Data {
     Configuration {
         raw: Bytes,
-        record: AVCDecoderConfigurationRecord,
+        record: Box<AVCDecoderConfigurationRecord>,
     },
+    /// dts/pts 单位为毫秒，有 B 帧时 pts 可能大于 dts；marker 表示访问单元在此结束
     Data {
-        timestamp: u32,
+        dts: u32,
+        pts: u32,
         data: Bytes,
+        marker: bool,
     },
 }
 
 impl H264Data {
     pub fn configuration(raw: Bytes, record: AVCDecoderConfigurationRecord) -> Self {
-        Self::Configuration { raw, record }
+        Self::Configuration {
+            raw,
+            record: Box::new(record),
+        }
     }
-    pub fn data(timestamp: u32, data: Bytes) -> Self {
-        Self::Data { timestamp, data }
+    /// 完整的访问单元
+    pub fn data(dts: u32, pts: u32, data: Bytes) -> Self {
+        Self::nal(dts, pts, data, true)
+    }
+
+    /// RTP 解包得到的 NAL，marker 取自 RTP 头，为访问单元的最后一个 NAL 时置位
+    pub fn nal(dts: u32, pts: u32, data: Bytes, marker: bool) -> Self {
+        Self::Data {
+            dts,
+            pts,
+            data,
+            marker,
+        }
+    }
+
+    /// FLV 中的 CompositionTime，即 pts - dts
+    pub fn composition_time(&self) -> i32 {
+        match self {
+            Self::Data { dts, pts, .. } => pts.wrapping_sub(*dts) as i32,
+            Self::Configuration { .. } => 0,
+        }
     }
 }
 
@@ -52,9 +78,99 @@ impl From<H264Data> for Bytes {
 // unsigned int(16) pictureParameterSetLength;
 // bit(8*pictureParameterSetLength) pictureParameterSetNALUnit;
 // }
+// if( profile_idc == 100 || profile_idc == 110 ||
+//     profile_idc == 122 || profile_idc == 144 )
+// {
+// bit(6) reserved = ‘111111’b;
+// unsigned int(2) chroma_format;
+// bit(5) reserved = ‘11111’b;
+// unsigned int(3) bit_depth_luma_minus8;
+// bit(5) reserved = ‘11111’b;
+// unsigned int(3) bit_depth_chroma_minus8;
+// unsigned int(8) numOfSequenceParameterSetExt;
+// for (i=0; i< numOfSequenceParameterSetExt; i++) {
+// unsigned int(16) sequenceParameterSetExtLength;
+// bit(8*sequenceParameterSetExtLength) sequenceParameterSetExtNALUnit;
+// }
+// }
 // }
 
-#[derive(Debug, Clone)]
+pub const NAL_UNIT_TYPE_SPS: u8 = 7;
+const NAL_UNIT_TYPE_PPS: u8 = 8;
+const NAL_UNIT_TYPE_SPS_EXT: u8 = 13;
+
+/// 解析 DecoderConfigurationRecord 时的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigurationRecordError {
+    /// 数据不足
+    UnexpectedEof { needed: usize, remaining: usize },
+
+    /// 不支持的 configurationVersion
+    UnsupportedVersion(u8),
+
+    /// 保留位不全为 1
+    InvalidReservedBits { field: &'static str, value: u8 },
+
+    /// lengthSizeMinusOne 只能为 0、1 或 3
+    InvalidLengthSize(u8),
+
+    /// 没有 SPS
+    MissingSequenceParameterSet,
+
+    /// 参数集长度为 0
+    EmptyParameterSet { field: &'static str },
+
+    /// 参数集的 NAL 类型与所在字段不符
+    InvalidNalUnitType {
+        field: &'static str,
+        expected: u8,
+        actual: u8,
+    },
+
+    /// 记录中的 profile 与 SPS 中的 profile_idc 不一致
+    ProfileMismatch { record: u8, sps: u8 },
+}
+
+impl fmt::Display for ConfigurationRecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEof { needed, remaining } => write!(
+                f,
+                "unexpected end of record: {} bytes needed, {} remaining",
+                needed, remaining
+            ),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported configuration version: {}", version)
+            }
+            Self::InvalidReservedBits { field, value } => {
+                write!(f, "invalid reserved bits before {}: {:#04x}", field, value)
+            }
+            Self::InvalidLengthSize(minus_one) => {
+                write!(f, "invalid NAL unit length size: {}", minus_one + 1)
+            }
+            Self::MissingSequenceParameterSet => write!(f, "no sequence parameter set"),
+            Self::EmptyParameterSet { field } => write!(f, "empty {}", field),
+            Self::InvalidNalUnitType {
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "invalid NAL unit type in {}: expected {}, got {}",
+                field, expected, actual
+            ),
+            Self::ProfileMismatch { record, sps } => write!(
+                f,
+                "profile indication {} does not match profile_idc {} in SPS",
+                record, sps
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigurationRecordError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AVCDecoderConfigurationRecord {
     pub configuration_version: u8,
     pub profile_indication: u8,
@@ -67,6 +183,20 @@ pub struct AVCDecoderConfigurationRecord {
     pub sequence_parameter_sets: Vec<(u16, Vec<u8>)>,
     pub num_of_picture_parameter_sets: u8,
     pub picture_parameter_sets: Vec<(u16, Vec<u8>)>,
+    pub high_profile_extension: Option<AVCHighProfileExtension>,
+}
+
+/// High profile（100/110/122/144）在记录末尾附加的字段，部分封装器（如旧版 FFmpeg）会省略
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AVCHighProfileExtension {
+    // 6 bits reserved
+    pub chroma_format: u8, // u2
+    // 5 bits reserved
+    pub bit_depth_luma_minus8: u8, // u3
+    // 5 bits reserved
+    pub bit_depth_chroma_minus8: u8, // u3
+    pub num_of_sequence_parameter_set_ext: u8,
+    pub sequence_parameter_set_ext: Vec<(u16, Vec<u8>)>,
 }
 
 impl AVCDecoderConfigurationRecord {
@@ -81,7 +211,99 @@ impl AVCDecoderConfigurationRecord {
             sequence_parameter_sets: vec![],
             num_of_picture_parameter_sets: 0,
             picture_parameter_sets: vec![],
+            high_profile_extension: None,
+        }
+    }
+
+    /// 从 FLV/MP4 中的 avcC 数据解析，并校验保留位、数量及长度
+    pub fn read_from<B: Buf>(mut buffer: B) -> Result<Self, ConfigurationRecordError> {
+        ensure_remaining(&buffer, 6)?;
+        let configuration_version = buffer.get_u8();
+        if configuration_version != 1 {
+            return Err(ConfigurationRecordError::UnsupportedVersion(
+                configuration_version,
+            ));
+        }
+        let profile_indication = buffer.get_u8();
+        let profile_compatibility = buffer.get_u8();
+        let level_indication = buffer.get_u8();
+
+        let length_size_minus_one = read_reserved(&mut buffer, 0b11111100, "lengthSizeMinusOne")?;
+        if length_size_minus_one == 2 {
+            return Err(ConfigurationRecordError::InvalidLengthSize(
+                length_size_minus_one,
+            ));
+        }
+
+        let num_of_sequence_parameter_sets =
+            read_reserved(&mut buffer, 0b11100000, "numOfSequenceParameterSets")?;
+        if num_of_sequence_parameter_sets == 0 {
+            return Err(ConfigurationRecordError::MissingSequenceParameterSet);
         }
+        let sequence_parameter_sets = read_parameter_sets(
+            &mut buffer,
+            num_of_sequence_parameter_sets,
+            "sequenceParameterSetNALUnit",
+            NAL_UNIT_TYPE_SPS,
+        )?;
+        if let Some((_, sps)) = sequence_parameter_sets.first() {
+            if let Some(&profile_idc) = sps.get(1) {
+                if profile_idc != profile_indication {
+                    return Err(ConfigurationRecordError::ProfileMismatch {
+                        record: profile_indication,
+                        sps: profile_idc,
+                    });
+                }
+            }
+        }
+
+        ensure_remaining(&buffer, 1)?;
+        let num_of_picture_parameter_sets = buffer.get_u8();
+        let picture_parameter_sets = read_parameter_sets(
+            &mut buffer,
+            num_of_picture_parameter_sets,
+            "pictureParameterSetNALUnit",
+            NAL_UNIT_TYPE_PPS,
+        )?;
+
+        let high_profile_extension =
+            if matches!(profile_indication, 100 | 110 | 122 | 144) && buffer.has_remaining() {
+                ensure_remaining(&buffer, 4)?;
+                let chroma_format = read_reserved(&mut buffer, 0b11111100, "chroma_format")?;
+                let bit_depth_luma_minus8 =
+                    read_reserved(&mut buffer, 0b11111000, "bit_depth_luma_minus8")?;
+                let bit_depth_chroma_minus8 =
+                    read_reserved(&mut buffer, 0b11111000, "bit_depth_chroma_minus8")?;
+                let num_of_sequence_parameter_set_ext = buffer.get_u8();
+                let sequence_parameter_set_ext = read_parameter_sets(
+                    &mut buffer,
+                    num_of_sequence_parameter_set_ext,
+                    "sequenceParameterSetExtNALUnit",
+                    NAL_UNIT_TYPE_SPS_EXT,
+                )?;
+                Some(AVCHighProfileExtension {
+                    chroma_format,
+                    bit_depth_luma_minus8,
+                    bit_depth_chroma_minus8,
+                    num_of_sequence_parameter_set_ext,
+                    sequence_parameter_set_ext,
+                })
+            } else {
+                None
+            };
+
+        Ok(Self {
+            configuration_version,
+            profile_indication,
+            profile_compatibility,
+            level_indication,
+            length_size_minus_one,
+            num_of_sequence_parameter_sets,
+            sequence_parameter_sets,
+            num_of_picture_parameter_sets,
+            picture_parameter_sets,
+            high_profile_extension,
+        })
     }
 
     pub fn add_sps(&mut self, data: Vec<u8>) {
@@ -112,5 +334,1028 @@ impl AVCDecoderConfigurationRecord {
             buffer.put_u16(*len);
             buffer.put_slice(pps.as_slice());
         }
+
+        if let Some(ext) = &self.high_profile_extension {
+            buffer.put_u8(0b11111100u8 | ext.chroma_format);
+            buffer.put_u8(0b11111000u8 | ext.bit_depth_luma_minus8);
+            buffer.put_u8(0b11111000u8 | ext.bit_depth_chroma_minus8);
+            buffer.put_u8(ext.num_of_sequence_parameter_set_ext);
+            for (len, sps_ext) in &ext.sequence_parameter_set_ext {
+                buffer.put_u16(*len);
+                buffer.put_slice(sps_ext.as_slice());
+            }
+        }
+    }
+}
+
+pub(crate) fn ensure_remaining<B: Buf>(
+    buffer: &B,
+    needed: usize,
+) -> Result<(), ConfigurationRecordError> {
+    if buffer.remaining() < needed {
+        Err(ConfigurationRecordError::UnexpectedEof {
+            needed,
+            remaining: buffer.remaining(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// 读取一个高位为保留位的字节，返回去掉保留位后的值
+pub(crate) fn read_reserved<B: Buf>(
+    buffer: &mut B,
+    mask: u8,
+    field: &'static str,
+) -> Result<u8, ConfigurationRecordError> {
+    ensure_remaining(buffer, 1)?;
+    let value = buffer.get_u8();
+    if value & mask != mask {
+        return Err(ConfigurationRecordError::InvalidReservedBits { field, value });
+    }
+    Ok(value & !mask)
+}
+
+/// 读取 count 个以 16 位长度为前缀的参数集，并校验 NAL 类型
+fn read_parameter_sets<B: Buf>(
+    buffer: &mut B,
+    count: u8,
+    field: &'static str,
+    expected: u8,
+) -> Result<Vec<(u16, Vec<u8>)>, ConfigurationRecordError> {
+    let mut sets = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        ensure_remaining(buffer, 2)?;
+        let len = buffer.get_u16();
+        if len == 0 {
+            return Err(ConfigurationRecordError::EmptyParameterSet { field });
+        }
+        ensure_remaining(buffer, len as usize)?;
+        let mut data = vec![0u8; len as usize];
+        buffer.copy_to_slice(&mut data);
+        let actual = data[0] & 0x1F;
+        if actual != expected {
+            return Err(ConfigurationRecordError::InvalidNalUnitType {
+                field,
+                expected,
+                actual,
+            });
+        }
+        sets.push((len, data));
+    }
+    Ok(sets)
+}
+
+pub const NAL_UNIT_TYPE_IDR: u8 = 5;
+pub const NAL_UNIT_TYPE_AUD: u8 = 9;
+
+pub fn nal_unit_type(nal: &[u8]) -> u8 {
+    nal.first().map(|header| header & 0x1F).unwrap_or(0)
+}
+
+/// 按起始码（00 00 01 或 00 00 00 01）拆分 Annex-B 码流，返回不含起始码的 NAL 单元
+pub fn split_annexb(data: &[u8]) -> Vec<&[u8]> {
+    let mut nal_units = vec![];
+    let mut start: Option<usize> = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = start {
+                nal_units.push(trim_trailing_zeros(&data[start..i]));
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        nal_units.push(trim_trailing_zeros(&data[start..]));
+    }
+    nal_units.retain(|nal| !nal.is_empty());
+    nal_units
+}
+
+fn trim_trailing_zeros(nal: &[u8]) -> &[u8] {
+    let end = nal.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &nal[..end]
+}
+
+/// 按 lengthSizeMinusOne 指定的长度前缀拆分 AVCC 码流
+pub fn split_avcc(data: &[u8], length_size_minus_one: u8) -> anyhow::Result<Vec<&[u8]>> {
+    let length_size = length_size_minus_one as usize + 1;
+    let mut nal_units = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.len() < length_size {
+            anyhow::bail!("Truncated AVCC NAL unit length: {} bytes left", rest.len());
+        }
+        let len = rest[..length_size]
+            .iter()
+            .fold(0usize, |len, b| (len << 8) | *b as usize);
+        rest = &rest[length_size..];
+        if len > rest.len() {
+            anyhow::bail!(
+                "Insufficient AVCC NAL unit data, len={}, data length={}",
+                len,
+                rest.len()
+            );
+        }
+        nal_units.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    Ok(nal_units)
+}
+
+/// 以长度前缀写入 NAL 单元
+pub fn write_avcc<'a, B: BufMut>(
+    nal_units: impl IntoIterator<Item = &'a [u8]>,
+    length_size_minus_one: u8,
+    mut buffer: B,
+) -> anyhow::Result<()> {
+    let length_size = length_size_minus_one as usize + 1;
+    for nal in nal_units {
+        if length_size < 4 && nal.len() >= 1 << (8 * length_size) {
+            anyhow::bail!(
+                "NAL unit of {} bytes does not fit in a {}-byte length",
+                nal.len(),
+                length_size
+            );
+        }
+        buffer.put_uint(nal.len() as u64, length_size);
+        buffer.put_slice(nal);
+    }
+    Ok(())
+}
+
+/// 将 Annex-B 帧转换为 FLV/MP4 中的 AVCC 帧，SPS/PPS 已在 sequence header 中，与 AUD 一起去掉
+pub fn annexb_to_avcc(data: &[u8], length_size_minus_one: u8) -> anyhow::Result<Vec<u8>> {
+    let mut avcc = Vec::with_capacity(data.len());
+    write_avcc(
+        split_annexb(data).into_iter().filter(|nal| {
+            !matches!(
+                nal_unit_type(nal),
+                NAL_UNIT_TYPE_SPS | NAL_UNIT_TYPE_PPS | NAL_UNIT_TYPE_AUD
+            )
+        }),
+        length_size_minus_one,
+        &mut avcc,
+    )?;
+    Ok(avcc)
+}
+
+/// 将 AVCC 帧转换为解码器使用的 Annex-B 帧
+pub fn avcc_to_annexb(data: &[u8], length_size_minus_one: u8) -> anyhow::Result<Vec<u8>> {
+    let mut annexb = Vec::with_capacity(data.len() + 16);
+    for nal in split_avcc(data, length_size_minus_one)? {
+        annexb.put_slice(&[0, 0, 0, 1]);
+        annexb.put_slice(nal);
+    }
+    Ok(annexb)
+}
+
+/// 去掉 NAL 单元中的防竞争字节（0x000003 中的 0x03），得到 RBSP
+pub fn nal_to_rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+/// 按位读取 RBSP，支持指数哥伦布编码
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> anyhow::Result<bool> {
+        let byte = match self.data.get(self.offset / 8) {
+            Some(byte) => *byte,
+            None => anyhow::bail!("Bitstream ended unexpectedly at bit {}", self.offset),
+        };
+        let bit = (byte >> (7 - self.offset % 8)) & 1;
+        self.offset += 1;
+        Ok(bit == 1)
+    }
+
+    pub fn read_bits(&mut self, n: u32) -> anyhow::Result<u32> {
+        debug_assert!(n <= 32);
+        let mut value = 0u32;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Ok(value)
+    }
+
+    pub fn skip_bits(&mut self, n: usize) -> anyhow::Result<()> {
+        if self.offset + n > self.data.len() * 8 {
+            anyhow::bail!("Bitstream ended unexpectedly at bit {}", self.offset);
+        }
+        self.offset += n;
+        Ok(())
+    }
+
+    /// ue(v)
+    pub fn read_ue(&mut self) -> anyhow::Result<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                anyhow::bail!("Invalid exp-Golomb code at bit {}", self.offset);
+            }
+        }
+        let suffix = self.read_bits(leading_zeros)? as u64;
+        Ok(((1u64 << leading_zeros) - 1 + suffix) as u32)
+    }
+
+    /// se(v)
+    pub fn read_se(&mut self) -> anyhow::Result<i32> {
+        let code = self.read_ue()? as i64;
+        Ok(if code % 2 == 1 {
+            ((code + 1) / 2) as i32
+        } else {
+            -(code / 2) as i32
+        })
+    }
+}
+
+/// SPS 中的剪裁区域，单位为 CropUnitX/CropUnitY
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCropping {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+/// VUI 中的时间信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingInfo {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub fixed_frame_rate_flag: bool,
+}
+
+/// VUI 参数（只解析到 timing_info）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VuiParameters {
+    /// 像素宽高比 sar_width:sar_height
+    pub sample_aspect_ratio: Option<(u16, u16)>,
+    pub video_full_range_flag: bool,
+    pub timing_info: Option<TimingInfo>,
+}
+
+/// H264 序列参数集
+///
+/// https://www.itu.int/rec/T-REC-H.264 7.3.2.1.1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceParameterSet {
+    pub profile_idc: u8,
+    pub constraint_set_flags: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub separate_colour_plane_flag: bool,
+    pub bit_depth_luma_minus8: u32,
+    pub bit_depth_chroma_minus8: u32,
+    pub log2_max_frame_num_minus4: u32,
+    pub pic_order_cnt_type: u32,
+    pub max_num_ref_frames: u32,
+    pub pic_width_in_mbs_minus1: u32,
+    pub pic_height_in_map_units_minus1: u32,
+    pub frame_mbs_only_flag: bool,
+    pub frame_cropping: Option<FrameCropping>,
+    pub vui_parameters: Option<VuiParameters>,
+}
+
+/// Table A-1 中 level 6.2 的最大帧大小（宏块数）
+const MAX_FRAME_SIZE_MBS: u32 = 139_264;
+const MAX_DIMENSION_MBS: u32 = 1_055;
+
+// Table E-1 – Meaning of sample aspect ratio indicator
+const SAMPLE_ASPECT_RATIOS: [(u16, u16); 17] = [
+    (0, 0),
+    (1, 1),
+    (12, 11),
+    (10, 11),
+    (16, 11),
+    (40, 33),
+    (24, 11),
+    (20, 11),
+    (32, 11),
+    (80, 33),
+    (18, 11),
+    (15, 11),
+    (64, 33),
+    (160, 99),
+    (4, 3),
+    (3, 2),
+    (2, 1),
+];
+const EXTENDED_SAR: u32 = 255;
+
+impl SequenceParameterSet {
+    /// 解析包含 NAL 头的 SPS
+    pub fn parse(nal: &[u8]) -> anyhow::Result<Self> {
+        match nal.first() {
+            Some(header) if header & 0x1F == NAL_UNIT_TYPE_SPS => {}
+            Some(header) => anyhow::bail!("NAL unit type {} is not SPS", header & 0x1F),
+            None => anyhow::bail!("Empty SPS"),
+        }
+
+        let rbsp = nal_to_rbsp(&nal[1..]);
+        let mut r = BitReader::new(&rbsp);
+
+        let profile_idc = r.read_bits(8)? as u8;
+        let constraint_set_flags = r.read_bits(8)? as u8;
+        let level_idc = r.read_bits(8)? as u8;
+        let seq_parameter_set_id = r.read_ue()?;
+
+        let mut chroma_format_idc = 1;
+        let mut separate_colour_plane_flag = false;
+        let mut bit_depth_luma_minus8 = 0;
+        let mut bit_depth_chroma_minus8 = 0;
+        if matches!(
+            profile_idc,
+            100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+        ) {
+            chroma_format_idc = r.read_ue()?;
+            if chroma_format_idc == 3 {
+                separate_colour_plane_flag = r.read_bit()?;
+            }
+            bit_depth_luma_minus8 = r.read_ue()?;
+            bit_depth_chroma_minus8 = r.read_ue()?;
+            // qpprime_y_zero_transform_bypass_flag
+            r.read_bit()?;
+            let seq_scaling_matrix_present_flag = r.read_bit()?;
+            if seq_scaling_matrix_present_flag {
+                let count = if chroma_format_idc != 3 { 8 } else { 12 };
+                for i in 0..count {
+                    if r.read_bit()? {
+                        skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+
+        let log2_max_frame_num_minus4 = r.read_ue()?;
+        let pic_order_cnt_type = r.read_ue()?;
+        match pic_order_cnt_type {
+            0 => {
+                // log2_max_pic_order_cnt_lsb_minus4
+                r.read_ue()?;
+            }
+            1 => {
+                // delta_pic_order_always_zero_flag
+                r.read_bit()?;
+                // offset_for_non_ref_pic
+                r.read_se()?;
+                // offset_for_top_to_bottom_field
+                r.read_se()?;
+                let num_ref_frames_in_pic_order_cnt_cycle = r.read_ue()?;
+                for _ in 0..num_ref_frames_in_pic_order_cnt_cycle {
+                    r.read_se()?;
+                }
+            }
+            2 => {}
+            t => anyhow::bail!("Invalid pic_order_cnt_type: {}", t),
+        }
+
+        let max_num_ref_frames = r.read_ue()?;
+        // gaps_in_frame_num_value_allowed_flag
+        r.read_bit()?;
+        let pic_width_in_mbs_minus1 = r.read_ue()?;
+        let pic_height_in_map_units_minus1 = r.read_ue()?;
+        let frame_mbs_only_flag = r.read_bit()?;
+        if !frame_mbs_only_flag {
+            // mb_adaptive_frame_field_flag
+            r.read_bit()?;
+        }
+        // direct_8x8_inference_flag
+        r.read_bit()?;
+
+        let frame_cropping = if r.read_bit()? {
+            Some(FrameCropping {
+                left: r.read_ue()?,
+                right: r.read_ue()?,
+                top: r.read_ue()?,
+                bottom: r.read_ue()?,
+            })
+        } else {
+            None
+        };
+
+        let vui_parameters = if r.read_bit()? {
+            Some(parse_vui_parameters(&mut r)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            profile_idc,
+            constraint_set_flags,
+            level_idc,
+            seq_parameter_set_id,
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            log2_max_frame_num_minus4,
+            pic_order_cnt_type,
+            max_num_ref_frames,
+            pic_width_in_mbs_minus1,
+            pic_height_in_map_units_minus1,
+            frame_mbs_only_flag,
+            frame_cropping,
+            vui_parameters,
+        })
+    }
+
+    fn chroma_array_type(&self) -> u32 {
+        if self.separate_colour_plane_flag {
+            0
+        } else {
+            self.chroma_format_idc
+        }
+    }
+
+    /// (CropUnitX, CropUnitY)
+    fn crop_units(&self) -> (u32, u32) {
+        let frame_height_factor = 2 - self.frame_mbs_only_flag as u32;
+        match self.chroma_array_type() {
+            0 => (1, frame_height_factor),
+            1 => (2, 2 * frame_height_factor),
+            2 => (2, frame_height_factor),
+            _ => (1, frame_height_factor),
+        }
+    }
+
+    /// 宏块数表示的宽度和高度
+    fn size_in_mbs(&self) -> (u32, u32) {
+        let width = self.pic_width_in_mbs_minus1.saturating_add(1);
+        let height = (self.pic_height_in_map_units_minus1.saturating_add(1))
+            .saturating_mul(2 - self.frame_mbs_only_flag as u32);
+        (width, height)
+    }
+
+    /// 剪裁后的宽度，数值异常时饱和而不溢出
+    pub fn width(&self) -> u32 {
+        let width = self.size_in_mbs().0.saturating_mul(16);
+        match &self.frame_cropping {
+            Some(crop) => width.saturating_sub(
+                self.crop_units()
+                    .0
+                    .saturating_mul(crop.left.saturating_add(crop.right)),
+            ),
+            None => width,
+        }
+    }
+
+    /// 剪裁后的高度，数值异常时饱和而不溢出
+    pub fn height(&self) -> u32 {
+        let height = self.size_in_mbs().1.saturating_mul(16);
+        match &self.frame_cropping {
+            Some(crop) => height.saturating_sub(
+                self.crop_units()
+                    .1
+                    .saturating_mul(crop.top.saturating_add(crop.bottom)),
+            ),
+            None => height,
+        }
+    }
+
+    /// VUI 中声明的帧率
+    pub fn frame_rate(&self) -> Option<f64> {
+        let timing = self.vui_parameters.as_ref()?.timing_info?;
+        if timing.num_units_in_tick == 0 || timing.time_scale == 0 {
+            return None;
+        }
+        Some(timing.time_scale as f64 / (2.0 * timing.num_units_in_tick as f64))
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        match self.profile_idc {
+            66 if self.constraint_set_flags & 0x40 != 0 => "Constrained Baseline",
+            66 => "Baseline",
+            77 => "Main",
+            88 => "Extended",
+            100 => "High",
+            110 => "High 10",
+            122 => "High 4:2:2",
+            244 => "High 4:4:4 Predictive",
+            44 => "CAVLC 4:4:4 Intra",
+            _ => "Unknown",
+        }
+    }
+
+    /// 以 3.1 之类的形式表示的 level
+    pub fn level(&self) -> f32 {
+        self.level_idc as f32 / 10.0
+    }
+
+    /// 检查 openh264 解码器能否解码：仅支持 8 bit、4:2:0、逐行扫描
+    pub fn ensure_decodable(&self) -> anyhow::Result<()> {
+        if self.chroma_format_idc != 1 {
+            anyhow::bail!(
+                "Unsupported chroma_format_idc {} ({} profile)",
+                self.chroma_format_idc,
+                self.profile_name()
+            );
+        }
+        if self.bit_depth_luma_minus8 != 0 || self.bit_depth_chroma_minus8 != 0 {
+            anyhow::bail!(
+                "Unsupported bit depth: luma {}, chroma {}",
+                self.bit_depth_luma_minus8 + 8,
+                self.bit_depth_chroma_minus8 + 8
+            );
+        }
+        if !self.frame_mbs_only_flag {
+            anyhow::bail!("Interlaced video is not supported");
+        }
+        // 不超过 level 6.2 的 MaxFS，单边不超过 sqrt(MaxFS * 8)
+        let (width, height) = self.size_in_mbs();
+        if width > MAX_DIMENSION_MBS
+            || height > MAX_DIMENSION_MBS
+            || width * height > MAX_FRAME_SIZE_MBS
+        {
+            anyhow::bail!("Unsupported frame size: {}x{} macroblocks", width, height);
+        }
+        if self.width() == 0 || self.height() == 0 {
+            anyhow::bail!("Frame cropping exceeds frame size");
+        }
+        Ok(())
+    }
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> anyhow::Result<()> {
+    let mut last_scale = 8i32;
+    let mut next_scale = 8i32;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = r.read_se()?;
+            next_scale = (last_scale + delta_scale + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+fn parse_vui_parameters(r: &mut BitReader) -> anyhow::Result<VuiParameters> {
+    let mut vui = VuiParameters::default();
+
+    if r.read_bit()? {
+        let aspect_ratio_idc = r.read_bits(8)?;
+        vui.sample_aspect_ratio = if aspect_ratio_idc == EXTENDED_SAR {
+            Some((r.read_bits(16)? as u16, r.read_bits(16)? as u16))
+        } else {
+            SAMPLE_ASPECT_RATIOS
+                .get(aspect_ratio_idc as usize)
+                .filter(|(w, h)| *w != 0 && *h != 0)
+                .copied()
+        };
+    }
+
+    // overscan_info_present_flag
+    if r.read_bit()? {
+        // overscan_appropriate_flag
+        r.read_bit()?;
+    }
+
+    // video_signal_type_present_flag
+    if r.read_bit()? {
+        // video_format
+        r.skip_bits(3)?;
+        vui.video_full_range_flag = r.read_bit()?;
+        // colour_description_present_flag
+        if r.read_bit()? {
+            // colour_primaries, transfer_characteristics, matrix_coefficients
+            r.skip_bits(24)?;
+        }
+    }
+
+    // chroma_loc_info_present_flag
+    if r.read_bit()? {
+        r.read_ue()?;
+        r.read_ue()?;
+    }
+
+    if r.read_bit()? {
+        vui.timing_info = Some(TimingInfo {
+            num_units_in_tick: r.read_bits(32)?,
+            time_scale: r.read_bits(32)?,
+            fixed_frame_rate_flag: r.read_bit()?,
+        });
+    }
+
+    Ok(vui)
+}
+
+impl AVCDecoderConfigurationRecord {
+    /// 从带有 SPS/PPS 的 Annex-B 关键帧生成配置，profile 和 level 取自 SPS
+    pub fn from_annexb(data: &[u8]) -> Option<Self> {
+        let nal_units = split_annexb(data);
+        let sps = nal_units
+            .iter()
+            .find(|nal| nal_unit_type(nal) == NAL_UNIT_TYPE_SPS && nal.len() >= 4)?;
+
+        let mut record = Self::new(sps[1], sps[3]);
+        record.profile_compatibility = sps[2];
+        for nal in &nal_units {
+            match nal_unit_type(nal) {
+                NAL_UNIT_TYPE_SPS => record.add_sps(nal.to_vec()),
+                NAL_UNIT_TYPE_PPS => record.add_pps(nal.to_vec()),
+                _ => {}
+            }
+        }
+
+        if record.picture_parameter_sets.is_empty() {
+            return None;
+        }
+        Some(record)
+    }
+
+    /// 将 SPS/PPS 转换为 Annex-B 格式，用于初始化解码器
+    pub fn to_annexb(&self) -> Vec<u8> {
+        let mut annexb = vec![];
+        for (_, nal) in self
+            .sequence_parameter_sets
+            .iter()
+            .chain(self.picture_parameter_sets.iter())
+        {
+            annexb.put_slice(&[0, 0, 0, 1]);
+            annexb.put_slice(nal);
+        }
+        annexb
+    }
+
+    /// 解析第一个 SPS
+    pub fn sps(&self) -> anyhow::Result<SequenceParameterSet> {
+        match self.sequence_parameter_sets.first() {
+            Some((_, sps)) => SequenceParameterSet::parse(sps),
+            None => anyhow::bail!("No SPS in AVCDecoderConfigurationRecord"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SRS 下发的 STAP-A 中的 SPS/PPS（见 rtc.rs）
+    const BASELINE_SPS: &[u8] = &[
+        0x67, 0x42, 0xc0, 0x15, 0x8c, 0x8d, 0x40, 0xa0, 0xf9, 0x00, 0xf0, 0x88, 0x46, 0xa0,
+    ];
+    const BASELINE_PPS: &[u8] = &[0x68, 0xce, 0x3c, 0x80];
+
+    // x264 风格的 1280x720 High profile SPS/PPS
+    const HIGH_SPS: &[u8] = &[
+        0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x40, 0x50, 0x05, 0xba, 0x10, 0x00, 0x00, 0x03, 0x00,
+        0x10, 0x00, 0x00, 0x03, 0x03, 0xc8, 0x40,
+    ];
+    const HIGH_PPS: &[u8] = &[0x68, 0xeb, 0xe3, 0xcb, 0x22, 0xc0];
+
+    fn record_bytes(
+        header: [u8; 5],
+        sps: &[u8],
+        pps: &[u8],
+        extension: Option<[u8; 4]>,
+    ) -> Vec<u8> {
+        let mut raw = header.to_vec();
+        raw.push(0xe1);
+        raw.put_u16(sps.len() as u16);
+        raw.put_slice(sps);
+        raw.push(0x01);
+        raw.put_u16(pps.len() as u16);
+        raw.put_slice(pps);
+        if let Some(extension) = extension {
+            raw.put_slice(&extension);
+        }
+        raw
+    }
+
+    fn round_trip(raw: &[u8]) -> AVCDecoderConfigurationRecord {
+        let record = AVCDecoderConfigurationRecord::read_from(raw).unwrap();
+        let mut written = BytesMut::new();
+        record.write_to(&mut written);
+        assert_eq!(written.as_ref(), raw);
+        record
+    }
+
+    #[test]
+    fn round_trip_baseline_record() {
+        let raw = record_bytes(
+            [0x01, 0x42, 0xc0, 0x15, 0xff],
+            BASELINE_SPS,
+            BASELINE_PPS,
+            None,
+        );
+        let record = round_trip(&raw);
+        assert_eq!(record.profile_indication, 0x42);
+        assert_eq!(record.profile_compatibility, 0xc0);
+        assert_eq!(record.level_indication, 0x15);
+        assert_eq!(record.length_size_minus_one, 3);
+        assert_eq!(record.sequence_parameter_sets[0].1, BASELINE_SPS);
+        assert_eq!(record.picture_parameter_sets[0].1, BASELINE_PPS);
+        assert!(record.high_profile_extension.is_none());
+    }
+
+    #[test]
+    fn round_trip_high_record_with_extension() {
+        let raw = record_bytes(
+            [0x01, 0x64, 0x00, 0x1f, 0xff],
+            HIGH_SPS,
+            HIGH_PPS,
+            Some([0xfd, 0xf8, 0xf8, 0x00]),
+        );
+        let record = round_trip(&raw);
+        let ext = record.high_profile_extension.unwrap();
+        assert_eq!(ext.chroma_format, 1);
+        assert_eq!(ext.bit_depth_luma_minus8, 0);
+        assert_eq!(ext.bit_depth_chroma_minus8, 0);
+        assert!(ext.sequence_parameter_set_ext.is_empty());
+    }
+
+    #[test]
+    fn round_trip_high_record_without_extension() {
+        let raw = record_bytes([0x01, 0x64, 0x00, 0x1f, 0xff], HIGH_SPS, HIGH_PPS, None);
+        assert!(round_trip(&raw).high_profile_extension.is_none());
+    }
+
+    #[test]
+    fn round_trip_built_record() {
+        let mut record = AVCDecoderConfigurationRecord::new(0x42, 0x15);
+        record.profile_compatibility = 0xc0;
+        record.add_sps(BASELINE_SPS.to_vec());
+        record.add_pps(BASELINE_PPS.to_vec());
+        let mut raw = BytesMut::new();
+        record.write_to(&mut raw);
+        round_trip(&raw);
+    }
+
+    #[test]
+    fn reject_invalid_records() {
+        let valid = record_bytes(
+            [0x01, 0x42, 0xc0, 0x15, 0xff],
+            BASELINE_SPS,
+            BASELINE_PPS,
+            None,
+        );
+        let read = |raw: &[u8]| AVCDecoderConfigurationRecord::read_from(raw).unwrap_err();
+
+        let mut raw = valid.clone();
+        raw[0] = 2;
+        assert_eq!(read(&raw), ConfigurationRecordError::UnsupportedVersion(2));
+
+        let mut raw = valid.clone();
+        raw[4] = 0x03;
+        assert_eq!(
+            read(&raw),
+            ConfigurationRecordError::InvalidReservedBits {
+                field: "lengthSizeMinusOne",
+                value: 0x03
+            }
+        );
+
+        let mut raw = valid.clone();
+        raw[4] = 0xfe;
+        assert_eq!(read(&raw), ConfigurationRecordError::InvalidLengthSize(2));
+
+        let mut raw = valid.clone();
+        raw[5] = 0xe0;
+        assert_eq!(
+            read(&raw),
+            ConfigurationRecordError::MissingSequenceParameterSet
+        );
+
+        let mut raw = valid.clone();
+        raw[5] = 0x01;
+        assert!(matches!(
+            read(&raw),
+            ConfigurationRecordError::InvalidReservedBits { .. }
+        ));
+
+        let mut raw = valid.clone();
+        raw[1] = 0x4d;
+        assert_eq!(
+            read(&raw),
+            ConfigurationRecordError::ProfileMismatch {
+                record: 0x4d,
+                sps: 0x42
+            }
+        );
+
+        let mut raw = valid.clone();
+        raw[8] = 0x68;
+        assert_eq!(
+            read(&raw),
+            ConfigurationRecordError::InvalidNalUnitType {
+                field: "sequenceParameterSetNALUnit",
+                expected: 7,
+                actual: 8
+            }
+        );
+
+        let mut raw = valid.clone();
+        raw[7] = 0xff;
+        assert_eq!(
+            read(&raw),
+            ConfigurationRecordError::UnexpectedEof {
+                needed: 0xff,
+                remaining: valid.len() - 8
+            }
+        );
+
+        assert_eq!(
+            read(&valid[..valid.len() - 1]),
+            ConfigurationRecordError::UnexpectedEof {
+                needed: 4,
+                remaining: 3
+            }
+        );
+    }
+
+    // 1920x1080，裁掉底部 8 行，帧率 29.97
+    const CROPPED_SPS: &[u8] = &[
+        0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0x84, 0x00, 0x00, 0x0f,
+        0xa4, 0x00, 0x03, 0xa9, 0x82, 0x10,
+    ];
+
+    #[test]
+    fn remove_emulation_prevention_bytes() {
+        assert_eq!(
+            nal_to_rbsp(&[0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03, 0x00, 0x03]),
+            vec![0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x03]
+        );
+    }
+
+    #[test]
+    fn read_exp_golomb() {
+        // 1 | 010 | 011 | 00100 | 00101
+        let data = [0b1010_0110, 0b0100_0010, 0b1000_0000];
+        let mut r = BitReader::new(&data);
+        assert_eq!(r.read_ue().unwrap(), 0);
+        assert_eq!(r.read_ue().unwrap(), 1);
+        assert_eq!(r.read_se().unwrap(), -1);
+        assert_eq!(r.read_se().unwrap(), 2);
+        assert_eq!(r.read_se().unwrap(), -2);
+        assert!(r.read_ue().is_err());
+    }
+
+    #[test]
+    fn parse_baseline_sps() {
+        let sps = SequenceParameterSet::parse(BASELINE_SPS).unwrap();
+        assert_eq!(sps.profile_name(), "Constrained Baseline");
+        assert_eq!(sps.level_idc, 21);
+        assert_eq!(sps.width(), 320);
+        assert_eq!(sps.height(), 240);
+        assert_eq!(sps.frame_rate(), None);
+        assert!(sps.ensure_decodable().is_ok());
+    }
+
+    #[test]
+    fn parse_high_sps_with_timing() {
+        let sps = SequenceParameterSet::parse(HIGH_SPS).unwrap();
+        assert_eq!(sps.profile_name(), "High");
+        assert_eq!(sps.level(), 3.1);
+        assert_eq!(sps.width(), 1280);
+        assert_eq!(sps.height(), 720);
+        assert_eq!(sps.frame_rate(), Some(30.0));
+    }
+
+    #[test]
+    fn parse_cropped_sps() {
+        let sps = SequenceParameterSet::parse(CROPPED_SPS).unwrap();
+        assert_eq!(sps.pic_height_in_map_units_minus1, 67);
+        assert_eq!(
+            sps.frame_cropping,
+            Some(FrameCropping {
+                bottom: 4,
+                ..Default::default()
+            })
+        );
+        assert_eq!(sps.width(), 1920);
+        assert_eq!(sps.height(), 1080);
+        let frame_rate = sps.frame_rate().unwrap();
+        assert!((frame_rate - 29.97).abs() < 0.01);
+    }
+
+    #[test]
+    fn reject_absurd_sps_sizes() {
+        let mut sps = SequenceParameterSet::parse(BASELINE_SPS).unwrap();
+        sps.pic_width_in_mbs_minus1 = u32::MAX;
+        assert_eq!(sps.width(), u32::MAX);
+        assert!(sps.ensure_decodable().is_err());
+
+        let mut sps = SequenceParameterSet::parse(BASELINE_SPS).unwrap();
+        sps.frame_cropping = Some(FrameCropping {
+            top: u32::MAX,
+            bottom: u32::MAX,
+            ..Default::default()
+        });
+        assert_eq!(sps.height(), 0);
+        assert!(sps.ensure_decodable().is_err());
+    }
+
+    #[test]
+    fn parse_sps_from_record() {
+        let raw = record_bytes(
+            [0x01, 0x42, 0xc0, 0x15, 0xff],
+            BASELINE_SPS,
+            BASELINE_PPS,
+            None,
+        );
+        let record = AVCDecoderConfigurationRecord::read_from(raw.as_slice()).unwrap();
+        assert_eq!(record.sps().unwrap().width(), 320);
+        assert!(SequenceParameterSet::parse(BASELINE_PPS).is_err());
+        assert!(SequenceParameterSet::parse(&BASELINE_SPS[..6]).is_err());
+    }
+
+    fn annexb_frame() -> Vec<u8> {
+        let mut frame = vec![0, 0, 0, 1, 0x09, 0xf0];
+        frame.extend_from_slice(&[0, 0, 0, 1]);
+        frame.extend_from_slice(BASELINE_SPS);
+        frame.extend_from_slice(&[0, 0, 1]);
+        frame.extend_from_slice(BASELINE_PPS);
+        frame.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88, 0x84, 0x00, 0x00, 0x03, 0x01]);
+        frame.extend_from_slice(&[0, 0, 1, 0x65, 0x00, 0x10, 0x00]);
+        frame
+    }
+
+    #[test]
+    fn split_annexb_nal_units() {
+        let frame = annexb_frame();
+        let nal_units = split_annexb(&frame);
+        let types: Vec<u8> = nal_units.iter().map(|nal| nal_unit_type(nal)).collect();
+        assert_eq!(types, vec![9, 7, 8, 5, 5]);
+        assert_eq!(nal_units[1], BASELINE_SPS);
+        assert_eq!(nal_units[2], BASELINE_PPS);
+        // 00 00 03 不是起始码；码流末尾的 trailing_zero_8bits 会被去掉
+        assert_eq!(nal_units[3], &[0x65, 0x88, 0x84, 0x00, 0x00, 0x03, 0x01]);
+        assert_eq!(nal_units[4], &[0x65, 0x00, 0x10]);
+        assert!(split_annexb(&[0x65, 0x88]).is_empty());
+    }
+
+    #[test]
+    fn convert_annexb_to_avcc() {
+        let avcc = annexb_to_avcc(&annexb_frame(), 3).unwrap();
+        assert_eq!(
+            avcc,
+            vec![
+                0, 0, 0, 7, 0x65, 0x88, 0x84, 0x00, 0x00, 0x03, 0x01, 0, 0, 0, 3, 0x65, 0x00, 0x10
+            ]
+        );
+
+        let avcc = annexb_to_avcc(&annexb_frame(), 1).unwrap();
+        assert_eq!(&avcc[..3], &[0, 7, 0x65]);
+
+        let mut large = vec![0, 0, 1, 0x65];
+        large.resize(300, 0xff);
+        assert!(annexb_to_avcc(&large, 0).is_err());
+    }
+
+    #[test]
+    fn convert_avcc_to_annexb() {
+        let avcc = [0, 0, 0, 3, 0x65, 0x00, 0x10, 0, 0, 0, 2, 0x41, 0x9a];
+        let annexb = avcc_to_annexb(&avcc, 3).unwrap();
+        assert_eq!(
+            annexb,
+            vec![0, 0, 0, 1, 0x65, 0x00, 0x10, 0, 0, 0, 1, 0x41, 0x9a]
+        );
+        assert_eq!(annexb_to_avcc(&annexb, 3).unwrap(), avcc);
+
+        assert!(avcc_to_annexb(&avcc[..12], 3).is_err());
+        assert!(avcc_to_annexb(&avcc[..2], 3).is_err());
+    }
+
+    #[test]
+    fn record_from_annexb_key_frame() {
+        let record = AVCDecoderConfigurationRecord::from_annexb(&annexb_frame()).unwrap();
+        assert_eq!(record.profile_indication, 0x42);
+        assert_eq!(record.profile_compatibility, 0xc0);
+        assert_eq!(record.level_indication, 0x15);
+        assert_eq!(record.sequence_parameter_sets[0].1, BASELINE_SPS);
+        assert_eq!(record.picture_parameter_sets[0].1, BASELINE_PPS);
+
+        let annexb = record.to_annexb();
+        assert_eq!(
+            AVCDecoderConfigurationRecord::from_annexb(&annexb),
+            Some(record)
+        );
+
+        assert!(AVCDecoderConfigurationRecord::from_annexb(&[0, 0, 1, 0x41, 0x9a]).is_none());
     }
 }
@@ -0,0 +1,728 @@
+#[cfg(test)]
+use crate::h264::{ensure_remaining, read_reserved, ConfigurationRecordError};
+use crate::h264::{nal_to_rbsp, split_annexb, write_avcc, BitReader};
+#[cfg(test)]
+use bytes::Buf;
+use bytes::{BufMut, Bytes, BytesMut};
+
+pub enum H265Data {
+    Configuration {
+        record: Box<HEVCDecoderConfigurationRecord>,
+    },
+    /// dts/pts 单位为毫秒，有 B 帧时 pts 可能大于 dts
+    Data { dts: u32, pts: u32, data: Bytes },
+}
+
+impl H265Data {
+    pub fn configuration(record: HEVCDecoderConfigurationRecord) -> Self {
+        Self::Configuration {
+            record: Box::new(record),
+        }
+    }
+    pub fn data(dts: u32, pts: u32, data: Bytes) -> Self {
+        Self::Data { dts, pts, data }
+    }
+
+    /// FLV 中的 CompositionTime，即 pts - dts
+    pub fn composition_time(&self) -> i32 {
+        match self {
+            Self::Data { dts, pts, .. } => pts.wrapping_sub(*dts) as i32,
+            Self::Configuration { .. } => 0,
+        }
+    }
+}
+
+pub const NAL_UNIT_TYPE_BLA_W_LP: u8 = 16;
+pub const NAL_UNIT_TYPE_RSV_IRAP_23: u8 = 23;
+pub const NAL_UNIT_TYPE_VPS: u8 = 32;
+pub const NAL_UNIT_TYPE_SPS: u8 = 33;
+pub const NAL_UNIT_TYPE_PPS: u8 = 34;
+pub const NAL_UNIT_TYPE_AUD: u8 = 35;
+const NAL_UNIT_TYPE_AP: u8 = 48;
+const NAL_UNIT_TYPE_FU: u8 = 49;
+
+/// HEVC 的 NAL 头为 2 字节，类型在第一个字节的 1~6 位
+pub fn nal_unit_type(nal: &[u8]) -> u8 {
+    nal.first().map(|header| (header >> 1) & 0x3F).unwrap_or(0)
+}
+
+/// IRAP（IDR/CRA/BLA）图像可以作为随机访问点
+pub fn is_irap(nal: &[u8]) -> bool {
+    (NAL_UNIT_TYPE_BLA_W_LP..=NAL_UNIT_TYPE_RSV_IRAP_23).contains(&nal_unit_type(nal))
+}
+
+/// 将 Annex-B 帧转换为 hvcC 对应的长度前缀格式，参数集和 AUD 会被去掉
+pub fn annexb_to_hvcc(data: &[u8], length_size_minus_one: u8) -> anyhow::Result<Vec<u8>> {
+    let mut hvcc = Vec::with_capacity(data.len());
+    write_avcc(
+        split_annexb(data).into_iter().filter(|nal| {
+            !matches!(
+                nal_unit_type(nal),
+                NAL_UNIT_TYPE_VPS | NAL_UNIT_TYPE_SPS | NAL_UNIT_TYPE_PPS | NAL_UNIT_TYPE_AUD
+            )
+        }),
+        length_size_minus_one,
+        &mut hvcc,
+    )?;
+    Ok(hvcc)
+}
+
+// aligned(8) class HEVCDecoderConfigurationRecord {
+// unsigned int(8) configurationVersion = 1;
+// unsigned int(2) general_profile_space;
+// unsigned int(1) general_tier_flag;
+// unsigned int(5) general_profile_idc;
+// unsigned int(32) general_profile_compatibility_flags;
+// unsigned int(48) general_constraint_indicator_flags;
+// unsigned int(8) general_level_idc;
+// bit(4) reserved = ‘1111’b;
+// unsigned int(12) min_spatial_segmentation_idc;
+// bit(6) reserved = ‘111111’b;
+// unsigned int(2) parallelismType;
+// bit(6) reserved = ‘111111’b;
+// unsigned int(2) chromaFormat;
+// bit(5) reserved = ‘11111’b;
+// unsigned int(3) bitDepthLumaMinus8;
+// bit(5) reserved = ‘11111’b;
+// unsigned int(3) bitDepthChromaMinus8;
+// bit(16) avgFrameRate;
+// bit(2) constantFrameRate;
+// bit(3) numTemporalLayers;
+// bit(1) temporalIdNested;
+// unsigned int(2) lengthSizeMinusOne;
+// unsigned int(8) numOfArrays;
+// for (j=0; j < numOfArrays; j++) {
+// bit(1) array_completeness;
+// unsigned int(1) reserved = 0;
+// unsigned int(6) NAL_unit_type;
+// unsigned int(16) numNalus;
+// for (i=0; i< numNalus; i++) {
+// unsigned int(16) nalUnitLength;
+// bit(8*nalUnitLength) nalUnit;
+// }
+// }
+// }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HEVCDecoderConfigurationRecord {
+    pub configuration_version: u8,
+    pub general_profile_space: u8, // u2
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8, // u5
+    pub general_profile_compatibility_flags: u32,
+    pub general_constraint_indicator_flags: u64, // u48
+    pub general_level_idc: u8,
+    // 4 bits reserved
+    pub min_spatial_segmentation_idc: u16, // u12
+    // 6 bits reserved
+    pub parallelism_type: u8, // u2
+    // 6 bits reserved
+    pub chroma_format: u8, // u2
+    // 5 bits reserved
+    pub bit_depth_luma_minus8: u8, // u3
+    // 5 bits reserved
+    pub bit_depth_chroma_minus8: u8, // u3
+    pub avg_frame_rate: u16,
+    pub constant_frame_rate: u8, // u2
+    pub num_temporal_layers: u8, // u3
+    pub temporal_id_nested: bool,
+    pub length_size_minus_one: u8, // u2
+    pub arrays: Vec<HEVCNalUnitArray>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HEVCNalUnitArray {
+    pub array_completeness: bool,
+    // 1 bit reserved
+    pub nal_unit_type: u8, // u6
+    pub nal_units: Vec<(u16, Vec<u8>)>,
+}
+
+impl HEVCDecoderConfigurationRecord {
+    /// 根据 SPS 中的 profile_tier_level 等信息创建配置，参数集需另行添加
+    pub fn new(sps: &SequenceParameterSet) -> Self {
+        Self {
+            configuration_version: 1,
+            general_profile_space: sps.general_profile_space,
+            general_tier_flag: sps.general_tier_flag,
+            general_profile_idc: sps.general_profile_idc,
+            general_profile_compatibility_flags: sps.general_profile_compatibility_flags,
+            general_constraint_indicator_flags: sps.general_constraint_indicator_flags,
+            general_level_idc: sps.general_level_idc,
+            min_spatial_segmentation_idc: 0,
+            parallelism_type: 0,
+            chroma_format: sps.chroma_format_idc as u8,
+            bit_depth_luma_minus8: sps.bit_depth_luma_minus8 as u8,
+            bit_depth_chroma_minus8: sps.bit_depth_chroma_minus8 as u8,
+            avg_frame_rate: 0,
+            constant_frame_rate: 0,
+            num_temporal_layers: sps.max_sub_layers_minus1 + 1,
+            temporal_id_nested: sps.temporal_id_nesting_flag,
+            length_size_minus_one: 0b11,
+            arrays: vec![],
+        }
+    }
+
+    pub fn add_nal_unit(&mut self, data: Vec<u8>) {
+        let nal_unit_type = nal_unit_type(&data);
+        let len = data.len() as u16;
+        match self
+            .arrays
+            .iter_mut()
+            .find(|array| array.nal_unit_type == nal_unit_type)
+        {
+            Some(array) => array.nal_units.push((len, data)),
+            None => self.arrays.push(HEVCNalUnitArray {
+                array_completeness: true,
+                nal_unit_type,
+                nal_units: vec![(len, data)],
+            }),
+        }
+    }
+
+    /// 从带有 VPS/SPS/PPS 的 Annex-B 关键帧生成配置
+    pub fn from_annexb(data: &[u8]) -> anyhow::Result<Option<Self>> {
+        let nal_units = split_annexb(data);
+        let sps = match nal_units
+            .iter()
+            .find(|nal| nal_unit_type(nal) == NAL_UNIT_TYPE_SPS)
+        {
+            Some(sps) => SequenceParameterSet::parse(sps)?,
+            None => return Ok(None),
+        };
+
+        let mut record = Self::new(&sps);
+        for nal in nal_units {
+            if matches!(
+                nal_unit_type(nal),
+                NAL_UNIT_TYPE_VPS | NAL_UNIT_TYPE_SPS | NAL_UNIT_TYPE_PPS
+            ) {
+                record.add_nal_unit(nal.to_vec());
+            }
+        }
+
+        if record.nal_units(NAL_UNIT_TYPE_VPS).next().is_none()
+            || record.nal_units(NAL_UNIT_TYPE_PPS).next().is_none()
+        {
+            return Ok(None);
+        }
+        Ok(Some(record))
+    }
+
+    pub fn nal_units(&self, nal_unit_type: u8) -> impl Iterator<Item = &[u8]> {
+        self.arrays
+            .iter()
+            .filter(move |array| array.nal_unit_type == nal_unit_type)
+            .flat_map(|array| array.nal_units.iter().map(|(_, nal)| nal.as_slice()))
+    }
+
+    /// 解析第一个 SPS
+    pub fn sps(&self) -> anyhow::Result<SequenceParameterSet> {
+        match self.nal_units(NAL_UNIT_TYPE_SPS).next() {
+            Some(sps) => SequenceParameterSet::parse(sps),
+            None => anyhow::bail!("No SPS in HEVCDecoderConfigurationRecord"),
+        }
+    }
+
+    /// 从 FLV/MP4 中的 hvcC 数据解析，并校验保留位、数量及长度
+    ///
+    /// 目前只转封装输出 hvcC，仅在测试中用于校验 [`Self::write_to`] 的结果
+    #[cfg(test)]
+    pub fn read_from<B: Buf>(mut buffer: B) -> Result<Self, ConfigurationRecordError> {
+        ensure_remaining(&buffer, 23)?;
+        let configuration_version = buffer.get_u8();
+        if configuration_version != 1 {
+            return Err(ConfigurationRecordError::UnsupportedVersion(
+                configuration_version,
+            ));
+        }
+        let byte = buffer.get_u8();
+        let general_profile_space = byte >> 6;
+        let general_tier_flag = byte & 0x20 != 0;
+        let general_profile_idc = byte & 0x1F;
+        let general_profile_compatibility_flags = buffer.get_u32();
+        let general_constraint_indicator_flags = buffer.get_uint(6);
+        let general_level_idc = buffer.get_u8();
+
+        let min_spatial_segmentation_idc = buffer.get_u16();
+        if min_spatial_segmentation_idc & 0xF000 != 0xF000 {
+            return Err(ConfigurationRecordError::InvalidReservedBits {
+                field: "min_spatial_segmentation_idc",
+                value: (min_spatial_segmentation_idc >> 8) as u8,
+            });
+        }
+        let min_spatial_segmentation_idc = min_spatial_segmentation_idc & 0x0FFF;
+        let parallelism_type = read_reserved(&mut buffer, 0b11111100, "parallelismType")?;
+        let chroma_format = read_reserved(&mut buffer, 0b11111100, "chromaFormat")?;
+        let bit_depth_luma_minus8 = read_reserved(&mut buffer, 0b11111000, "bitDepthLumaMinus8")?;
+        let bit_depth_chroma_minus8 =
+            read_reserved(&mut buffer, 0b11111000, "bitDepthChromaMinus8")?;
+        let avg_frame_rate = buffer.get_u16();
+
+        let byte = buffer.get_u8();
+        let constant_frame_rate = byte >> 6;
+        let num_temporal_layers = (byte >> 3) & 0b111;
+        let temporal_id_nested = byte & 0b100 != 0;
+        let length_size_minus_one = byte & 0b11;
+        if length_size_minus_one == 2 {
+            return Err(ConfigurationRecordError::InvalidLengthSize(
+                length_size_minus_one,
+            ));
+        }
+
+        let num_of_arrays = buffer.get_u8();
+        let mut arrays = Vec::with_capacity(num_of_arrays as usize);
+        for _ in 0..num_of_arrays {
+            ensure_remaining(&buffer, 3)?;
+            let byte = buffer.get_u8();
+            let array_completeness = byte & 0x80 != 0;
+            let nal_unit_type = byte & 0x3F;
+            let num_nalus = buffer.get_u16();
+            let mut nal_units = Vec::with_capacity(num_nalus as usize);
+            for _ in 0..num_nalus {
+                ensure_remaining(&buffer, 2)?;
+                let len = buffer.get_u16();
+                if len == 0 {
+                    return Err(ConfigurationRecordError::EmptyParameterSet { field: "nalUnit" });
+                }
+                ensure_remaining(&buffer, len as usize)?;
+                let mut data = vec![0u8; len as usize];
+                buffer.copy_to_slice(&mut data);
+                let actual = self::nal_unit_type(&data);
+                if actual != nal_unit_type {
+                    return Err(ConfigurationRecordError::InvalidNalUnitType {
+                        field: "nalUnit",
+                        expected: nal_unit_type,
+                        actual,
+                    });
+                }
+                nal_units.push((len, data));
+            }
+            arrays.push(HEVCNalUnitArray {
+                array_completeness,
+                nal_unit_type,
+                nal_units,
+            });
+        }
+
+        let record = Self {
+            configuration_version,
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags,
+            general_level_idc,
+            min_spatial_segmentation_idc,
+            parallelism_type,
+            chroma_format,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            avg_frame_rate,
+            constant_frame_rate,
+            num_temporal_layers,
+            temporal_id_nested,
+            length_size_minus_one,
+            arrays,
+        };
+        if record.nal_units(NAL_UNIT_TYPE_SPS).next().is_none() {
+            return Err(ConfigurationRecordError::MissingSequenceParameterSet);
+        }
+        Ok(record)
+    }
+
+    pub fn write_to<B: BufMut>(&self, mut buffer: B) {
+        buffer.put_u8(self.configuration_version);
+        buffer.put_u8(
+            self.general_profile_space << 6
+                | (self.general_tier_flag as u8) << 5
+                | self.general_profile_idc,
+        );
+        buffer.put_u32(self.general_profile_compatibility_flags);
+        buffer.put_uint(self.general_constraint_indicator_flags, 6);
+        buffer.put_u8(self.general_level_idc);
+        buffer.put_u16(0xF000 | self.min_spatial_segmentation_idc);
+        buffer.put_u8(0b11111100 | self.parallelism_type);
+        buffer.put_u8(0b11111100 | self.chroma_format);
+        buffer.put_u8(0b11111000 | self.bit_depth_luma_minus8);
+        buffer.put_u8(0b11111000 | self.bit_depth_chroma_minus8);
+        buffer.put_u16(self.avg_frame_rate);
+        buffer.put_u8(
+            self.constant_frame_rate << 6
+                | self.num_temporal_layers << 3
+                | (self.temporal_id_nested as u8) << 2
+                | self.length_size_minus_one,
+        );
+        buffer.put_u8(self.arrays.len() as u8);
+        for array in &self.arrays {
+            buffer.put_u8((array.array_completeness as u8) << 7 | array.nal_unit_type);
+            buffer.put_u16(array.nal_units.len() as u16);
+            for (len, nal) in &array.nal_units {
+                buffer.put_u16(*len);
+                buffer.put_slice(nal);
+            }
+        }
+    }
+
+    /// 将 VPS/SPS/PPS 转换为 Annex-B 格式
+    #[cfg(test)]
+    pub fn to_annexb(&self) -> Vec<u8> {
+        let mut annexb = vec![];
+        for array in &self.arrays {
+            for (_, nal) in &array.nal_units {
+                annexb.put_slice(&[0, 0, 0, 1]);
+                annexb.put_slice(nal);
+            }
+        }
+        annexb
+    }
+}
+
+/// HEVC 序列参数集，只解析到位深
+///
+/// https://www.itu.int/rec/T-REC-H.265 7.3.2.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceParameterSet {
+    pub max_sub_layers_minus1: u8,
+    pub temporal_id_nesting_flag: bool,
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub separate_colour_plane_flag: bool,
+    pub pic_width_in_luma_samples: u32,
+    pub pic_height_in_luma_samples: u32,
+    /// 左、右、上、下
+    pub conformance_window: Option<(u32, u32, u32, u32)>,
+    pub bit_depth_luma_minus8: u32,
+    pub bit_depth_chroma_minus8: u32,
+}
+
+impl SequenceParameterSet {
+    /// 解析包含 NAL 头的 SPS
+    pub fn parse(nal: &[u8]) -> anyhow::Result<Self> {
+        if nal.len() < 3 {
+            anyhow::bail!("SPS too short: {} bytes", nal.len());
+        }
+        if nal_unit_type(nal) != NAL_UNIT_TYPE_SPS {
+            anyhow::bail!("NAL unit type {} is not SPS", nal_unit_type(nal));
+        }
+
+        let rbsp = nal_to_rbsp(&nal[2..]);
+        let mut r = BitReader::new(&rbsp);
+
+        // sps_video_parameter_set_id
+        r.skip_bits(4)?;
+        let max_sub_layers_minus1 = r.read_bits(3)? as u8;
+        let temporal_id_nesting_flag = r.read_bit()?;
+
+        // profile_tier_level(1, sps_max_sub_layers_minus1)
+        let general_profile_space = r.read_bits(2)? as u8;
+        let general_tier_flag = r.read_bit()?;
+        let general_profile_idc = r.read_bits(5)? as u8;
+        let general_profile_compatibility_flags = r.read_bits(32)?;
+        let general_constraint_indicator_flags =
+            (r.read_bits(16)? as u64) << 32 | r.read_bits(32)? as u64;
+        let general_level_idc = r.read_bits(8)? as u8;
+        let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1 as usize);
+        for _ in 0..max_sub_layers_minus1 {
+            // sub_layer_profile_present_flag, sub_layer_level_present_flag
+            sub_layers.push((r.read_bit()?, r.read_bit()?));
+        }
+        if max_sub_layers_minus1 > 0 {
+            // reserved_zero_2bits
+            r.skip_bits(2 * (8 - max_sub_layers_minus1 as usize))?;
+        }
+        for (profile_present, level_present) in sub_layers {
+            if profile_present {
+                r.skip_bits(88)?;
+            }
+            if level_present {
+                r.skip_bits(8)?;
+            }
+        }
+
+        let seq_parameter_set_id = r.read_ue()?;
+        let chroma_format_idc = r.read_ue()?;
+        let separate_colour_plane_flag = if chroma_format_idc == 3 {
+            r.read_bit()?
+        } else {
+            false
+        };
+        let pic_width_in_luma_samples = r.read_ue()?;
+        let pic_height_in_luma_samples = r.read_ue()?;
+        let conformance_window = if r.read_bit()? {
+            Some((r.read_ue()?, r.read_ue()?, r.read_ue()?, r.read_ue()?))
+        } else {
+            None
+        };
+        let bit_depth_luma_minus8 = r.read_ue()?;
+        let bit_depth_chroma_minus8 = r.read_ue()?;
+
+        Ok(Self {
+            max_sub_layers_minus1,
+            temporal_id_nesting_flag,
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags,
+            general_level_idc,
+            seq_parameter_set_id,
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            pic_width_in_luma_samples,
+            pic_height_in_luma_samples,
+            conformance_window,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+        })
+    }
+
+    /// (SubWidthC, SubHeightC)
+    fn sub_sampling(&self) -> (u32, u32) {
+        match (self.chroma_format_idc, self.separate_colour_plane_flag) {
+            (1, _) => (2, 2),
+            (2, _) => (2, 1),
+            _ => (1, 1),
+        }
+    }
+
+    /// 去掉 conformance window 后的宽度
+    pub fn width(&self) -> u32 {
+        match self.conformance_window {
+            Some((left, right, _, _)) => self
+                .pic_width_in_luma_samples
+                .saturating_sub(self.sub_sampling().0 * (left + right)),
+            None => self.pic_width_in_luma_samples,
+        }
+    }
+
+    /// 去掉 conformance window 后的高度
+    pub fn height(&self) -> u32 {
+        match self.conformance_window {
+            Some((_, _, top, bottom)) => self
+                .pic_height_in_luma_samples
+                .saturating_sub(self.sub_sampling().1 * (top + bottom)),
+            None => self.pic_height_in_luma_samples,
+        }
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        match self.general_profile_idc {
+            1 => "Main",
+            2 => "Main 10",
+            3 => "Main Still Picture",
+            4 => "Range Extensions",
+            _ => "Unknown",
+        }
+    }
+
+    /// general_level_idc 为 30 倍的 level
+    pub fn level(&self) -> f32 {
+        self.general_level_idc as f32 / 30.0
+    }
+}
+
+/// H265 RTP 解包，支持单 NAL、AP（聚合包）及 FU（分片单元），输出 Annex-B 格式
+///
+/// https://datatracker.ietf.org/doc/html/rfc7798#section-4.4
+#[derive(Debug, Default)]
+pub struct H265Depacketizer {
+    fragment: Option<BytesMut>,
+}
+
+impl H265Depacketizer {
+    /// 返回本包中完整的 NAL 单元，分片未结束时返回空
+    pub fn depacketize(&mut self, payload: &[u8]) -> anyhow::Result<Bytes> {
+        if payload.len() < 3 {
+            anyhow::bail!("H265 RTP payload too short: {} bytes", payload.len());
+        }
+        if payload[0] & 0x80 != 0 {
+            anyhow::bail!("H265 NAL unit header forbidden bit is set");
+        }
+
+        let mut output = BytesMut::new();
+        match nal_unit_type(payload) {
+            NAL_UNIT_TYPE_AP => {
+                let mut rest = &payload[2..];
+                while !rest.is_empty() {
+                    if rest.len() < 2 {
+                        anyhow::bail!("Truncated NAL unit size in H265 aggregation packet");
+                    }
+                    let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+                    rest = &rest[2..];
+                    if len > rest.len() {
+                        anyhow::bail!(
+                            "Insufficient nal unit data, len={}, data length={}",
+                            len,
+                            rest.len()
+                        );
+                    }
+                    output.put_slice(&[0, 0, 0, 1]);
+                    output.put_slice(&rest[..len]);
+                    rest = &rest[len..];
+                }
+            }
+            NAL_UNIT_TYPE_FU => {
+                let fu_header = payload[2];
+                let start = fu_header & 0x80 != 0;
+                let end = fu_header & 0x40 != 0;
+                let fu_type = fu_header & 0x3F;
+
+                if start {
+                    let mut fragment = BytesMut::with_capacity(payload.len() * 4);
+                    fragment.put_u8((payload[0] & 0x81) | (fu_type << 1));
+                    fragment.put_u8(payload[1]);
+                    self.fragment = Some(fragment);
+                }
+
+                match &mut self.fragment {
+                    Some(fragment) => fragment.put_slice(&payload[3..]),
+                    None => {
+                        // 丢失了分片的起始包，丢弃剩余分片
+                        return Ok(Bytes::new());
+                    }
+                }
+
+                if end {
+                    if let Some(fragment) = self.fragment.take() {
+                        output.put_slice(&[0, 0, 0, 1]);
+                        output.put_slice(&fragment);
+                    }
+                }
+            }
+            t if t > NAL_UNIT_TYPE_FU => {
+                log::debug!("Ignore unsupported H265 RTP payload type: {}", t);
+            }
+            _ => {
+                output.put_slice(&[0, 0, 0, 1]);
+                output.put_slice(payload);
+            }
+        }
+
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // x265 输出的 1280x720 Main profile 参数集
+    const VPS: &[u8] = &[
+        0x40, 0x01, 0x0c, 0x01, 0xff, 0xff, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00,
+        0x03, 0x00, 0x00, 0x03, 0x00, 0x5d, 0x95, 0x98, 0x09,
+    ];
+    const SPS: &[u8] = &[
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x5d, 0xa0, 0x02, 0x80, 0x80, 0x2d, 0x16, 0x59, 0x59, 0xa4, 0x93, 0x2b, 0xc0,
+        0x5a, 0x70, 0x80, 0x00, 0x01, 0xf4, 0x80, 0x00, 0x3a, 0x98, 0x04,
+    ];
+    const PPS: &[u8] = &[0x44, 0x01, 0xc1, 0x72, 0xb4, 0x62, 0x40];
+    const IDR: &[u8] = &[0x26, 0x01, 0xaf, 0x06, 0xb8];
+
+    fn key_frame() -> Vec<u8> {
+        let mut frame = vec![];
+        for nal in [VPS, SPS, PPS, IDR] {
+            frame.extend_from_slice(&[0, 0, 0, 1]);
+            frame.extend_from_slice(nal);
+        }
+        frame
+    }
+
+    #[test]
+    fn parse_sps() {
+        let sps = SequenceParameterSet::parse(SPS).unwrap();
+        assert_eq!(sps.profile_name(), "Main");
+        assert_eq!(sps.general_level_idc, 93);
+        assert_eq!(sps.general_profile_compatibility_flags, 0x6000_0000);
+        assert_eq!(sps.general_constraint_indicator_flags, 0x9000_0000_0000);
+        assert_eq!(sps.width(), 1280);
+        assert_eq!(sps.height(), 720);
+    }
+
+    #[test]
+    fn round_trip_record() {
+        let record = HEVCDecoderConfigurationRecord::from_annexb(&key_frame())
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.general_profile_idc, 1);
+        assert_eq!(record.chroma_format, 1);
+        assert_eq!(record.num_temporal_layers, 1);
+        assert!(record.temporal_id_nested);
+        assert_eq!(record.arrays.len(), 3);
+        assert_eq!(record.sps().unwrap().width(), 1280);
+
+        let mut raw = BytesMut::new();
+        record.write_to(&mut raw);
+        assert_eq!(raw.len(), 23 + 3 * 5 + VPS.len() + SPS.len() + PPS.len());
+        let parsed = HEVCDecoderConfigurationRecord::read_from(raw.as_ref()).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(split_annexb(&parsed.to_annexb()), vec![VPS, SPS, PPS]);
+
+        let mut invalid = raw.to_vec();
+        invalid[13] = 0x00;
+        assert!(matches!(
+            HEVCDecoderConfigurationRecord::read_from(invalid.as_slice()),
+            Err(ConfigurationRecordError::InvalidReservedBits { .. })
+        ));
+        assert!(matches!(
+            HEVCDecoderConfigurationRecord::read_from(&raw[..raw.len() - 1]),
+            Err(ConfigurationRecordError::UnexpectedEof { .. })
+        ));
+    }
+
+    #[test]
+    fn convert_annexb_to_hvcc() {
+        let hvcc = annexb_to_hvcc(&key_frame(), 3).unwrap();
+        assert_eq!(hvcc, [&[0, 0, 0, 5], IDR].concat());
+        assert!(is_irap(IDR));
+        assert!(!is_irap(&[0x02, 0x01]));
+    }
+
+    #[test]
+    fn depacketize_single_and_aggregation_packets() {
+        let mut depacketizer = H265Depacketizer::default();
+        assert_eq!(
+            depacketizer.depacketize(IDR).unwrap().as_ref(),
+            [&[0, 0, 0, 1], IDR].concat()
+        );
+
+        let mut ap = vec![0x60, 0x01];
+        for nal in [VPS, SPS, PPS] {
+            ap.put_u16(nal.len() as u16);
+            ap.put_slice(nal);
+        }
+        let output = depacketizer.depacketize(&ap).unwrap();
+        assert_eq!(split_annexb(&output), vec![VPS, SPS, PPS]);
+
+        ap.truncate(ap.len() - 1);
+        assert!(depacketizer.depacketize(&ap).is_err());
+    }
+
+    #[test]
+    fn depacketize_fragmentation_units() {
+        let mut depacketizer = H265Depacketizer::default();
+        // IDR_W_RADL(19) 拆成三个分片
+        let header = [0x62, 0x01];
+        let fragments = [
+            [&header[..], &[0x80 | 19], &[0xaf, 0x06]].concat(),
+            [&header[..], &[19], &[0xb8]].concat(),
+            [&header[..], &[0x40 | 19], &[0x01, 0x02]].concat(),
+        ];
+        assert!(depacketizer.depacketize(&fragments[0]).unwrap().is_empty());
+        assert!(depacketizer.depacketize(&fragments[1]).unwrap().is_empty());
+        assert_eq!(
+            depacketizer.depacketize(&fragments[2]).unwrap().as_ref(),
+            &[0, 0, 0, 1, 0x26, 0x01, 0xaf, 0x06, 0xb8, 0x01, 0x02]
+        );
+
+        // 缺少起始分片时丢弃
+        assert!(depacketizer.depacketize(&fragments[2]).unwrap().is_empty());
+    }
+}
@@ -1,15 +1,37 @@
 mod api;
 mod codec;
+mod compositor;
+mod control;
+mod filter;
+mod graph;
 mod h264;
+mod h265;
 mod param;
 mod rtc;
 mod rtmp;
+mod server;
+mod speaker;
+mod template;
+mod text;
+mod yuv;
 
 use crate::api::PlayParam;
+use crate::compositor::{Compositor, FrameSlot};
+use crate::filter::FilterRegistry;
+#[cfg(feature = "ffmpeg")]
+use crate::graph::GraphCompositor;
 use crate::h264::H264Data;
-use crate::rtmp::RtmpConnection;
-use clap::Parser;
+use crate::param::{Color, JobConfig, RateControl, VideoPosition};
+use crate::rtc::{PublishTarget, TrackSenders};
+use crate::rtmp::{RtmpConnection, VideoPacket};
+use crate::speaker::SpeakerDetector;
+use clap::{Args, Parser};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU8;
+use std::sync::Arc;
 use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::Notify;
 
 #[derive(Debug, Parser)]
 struct Opts {
@@ -27,6 +49,115 @@ struct Opts {
 
     #[clap(short = 'l', long, default_value = "INFO")]
     log_level: log::LevelFilter,
+
+    /// WebRTC 推流地址：webrtc://host[:port]/app/stream（SRS）或 http(s)://...（WHIP）
+    #[clap(long)]
+    publish: Option<PublishTarget>,
+
+    /// WHIP 鉴权令牌
+    #[clap(long)]
+    publish_token: Option<String>,
+
+    #[clap(flatten)]
+    job: JobOpts,
+
+    /// HTTP 控制接口监听地址，如 0.0.0.0:8080
+    #[clap(long)]
+    api_listen: Option<SocketAddr>,
+
+    /// 透传模式：单路源且无布局时不解码，直接转封装为 FLV
+    #[clap(long)]
+    passthrough: bool,
+
+    /// 使用 ffmpeg 滤镜图合成，不支持图片、文字叠加及过渡动画
+    #[cfg(feature = "ffmpeg")]
+    #[clap(long)]
+    ffmpeg_graph: bool,
+}
+
+/// 任务参数，未指定的项取配置文件中的值
+#[derive(Debug, Args)]
+struct JobOpts {
+    /// 任务配置文件（JSON）
+    #[clap(short = 'c', long)]
+    config: Option<PathBuf>,
+
+    /// 输出画布宽度
+    #[clap(long)]
+    width: Option<u32>,
+
+    /// 输出画布高度
+    #[clap(long)]
+    height: Option<u32>,
+
+    /// 输出帧率
+    #[clap(long)]
+    fps: Option<u32>,
+
+    /// 画布背景色，格式为 #RRGGBB
+    #[clap(long)]
+    background: Option<Color>,
+
+    /// 目标码率（bps）
+    #[clap(long)]
+    bitrate: Option<u32>,
+
+    /// 最大码率（bps）
+    #[clap(long)]
+    max_bitrate: Option<u32>,
+
+    /// 关键帧间隔（帧）
+    #[clap(long)]
+    keyframe_interval: Option<u32>,
+
+    /// 码率控制模式：quality、bitrate（cbr）、buffer、timestamp、off
+    #[clap(long)]
+    rate_control: Option<RateControl>,
+
+    /// 码率超限时是否允许跳帧
+    #[clap(long)]
+    skip_frame: Option<bool>,
+
+    /// 编码线程数，0 为自动
+    #[clap(long)]
+    threads: Option<u16>,
+}
+
+impl JobOpts {
+    fn into_config(self) -> anyhow::Result<JobConfig> {
+        let mut config = match &self.config {
+            Some(path) => JobConfig::load(path)?,
+            None => JobConfig::default(),
+        };
+        let canvas = &mut config.canvas;
+        canvas.width = self.width.unwrap_or(canvas.width);
+        canvas.height = self.height.unwrap_or(canvas.height);
+        canvas.fps = self.fps.unwrap_or(canvas.fps);
+        canvas.background = self.background.unwrap_or(canvas.background);
+        canvas.validate()?;
+
+        let encoder = &mut config.encoder;
+        encoder.bitrate = self.bitrate.unwrap_or(encoder.bitrate);
+        encoder.max_bitrate = self.max_bitrate.or(encoder.max_bitrate);
+        encoder.keyframe_interval = self.keyframe_interval.unwrap_or(encoder.keyframe_interval);
+        encoder.rate_control = self.rate_control.unwrap_or(encoder.rate_control);
+        encoder.skip_frame = self.skip_frame.unwrap_or(encoder.skip_frame);
+        encoder.threads = self.threads.unwrap_or(encoder.threads);
+        encoder.validate()?;
+        config.fallback.validate()?;
+        config.layout.validate(&config.canvas, &filter_registry())?;
+
+        Ok(config)
+    }
+}
+
+/// tile 滤镜链可引用的滤镜，默认含内置的 blur 及 color
+///
+/// 自定义滤镜实现 [`filter::VideoFilter`] 后在此注册，如
+/// `registry.register_with("name", |config: Config| Ok(Filter::new(config)))`，
+/// 布局中 tile 的 filters 按名称引用
+fn filter_registry() -> FilterRegistry {
+    FilterRegistry::default()
 }
 
 #[tokio::main]
@@ -37,17 +168,55 @@ async fn main() -> anyhow::Result<()> {
         port,
         tid,
         log_level,
+        publish,
+        publish_token,
+        job,
+        api_listen,
+        passthrough,
+        #[cfg(feature = "ffmpeg")]
+        ffmpeg_graph,
     } = Opts::parse();
 
+    let JobConfig {
+        canvas,
+        encoder,
+        fallback,
+        mut layout,
+        speaker,
+    } = job.into_config()?;
+
     // ffmpeg::init()?;
 
     env_logger::builder().filter(None, log_level).init();
 
-    let (h264_sender, h264_receiver) = unbounded_channel();
+    if layout.videos.is_empty() && layout.template.is_none() {
+        // 单路源铺满画布
+        let mut position = VideoPosition::new(tid.clone(), 0, 0, canvas.width, canvas.height);
+        position.fallback = fallback;
+        layout.videos.push(position);
+    }
+
+    let speaker = SpeakerDetector::new(speaker);
+    let (control, updates) = control::channel(
+        canvas.clone(),
+        filter_registry(),
+        encoder,
+        layout,
+        speaker.clone(),
+    );
+    if let Some(addr) = api_listen {
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(addr, control).await {
+                log::error!("API server error: {}", e);
+            }
+        });
+    }
+
+    let (video_sender, video_receiver) = unbounded_channel();
 
     tokio::spawn(async move {
         if let Err(e) = async move {
-            let mut rtmp_conn = RtmpConnection::connect(&output, h264_receiver).await?;
+            let mut rtmp_conn = RtmpConnection::connect(&output, video_receiver).await?;
             log::info!("[rtmp handshaked] addr: {}", output,);
 
             rtmp_conn.publish("gengteng").await?;
@@ -62,17 +231,91 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
+    let (rtc_sender, audio_sender, publisher) = match publish {
+        Some(target) => {
+            let (rtc_sender, rtc_receiver) = unbounded_channel();
+            let (audio_sender, audio_receiver) = unbounded_channel();
+            let publisher =
+                rtc::publish(target, publish_token, rtc_receiver, audio_receiver).await?;
+            (Some(rtc_sender), Some(audio_sender), Some(publisher))
+        }
+        None => (None, None, None),
+    };
+
     let (sender, receiver) = tokio::sync::mpsc::channel::<H264Data>(32);
 
-    tokio::task::spawn_blocking(move || {
-        if let Err(e) = codec::decode(receiver, h264_sender) {
-            log::error!("ff::decode error: {}", e);
+    let h264_sender = video_sender.clone();
+    let keyframe_request = Arc::new(Notify::new());
+    let video_orientation = Arc::new(AtomicU8::new(0));
+    if passthrough {
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = codec::remux(receiver, h264_sender, rtc_sender) {
+                log::error!("codec::remux error: {}", e);
+            }
+        });
+    } else {
+        let slot = FrameSlot::default();
+        let mut compositor = Compositor::new(canvas, filter_registry());
+        compositor.add_source(tid.clone(), slot.clone());
+        compositor.set_speaker_detector(speaker.clone());
+
+        #[cfg(feature = "ffmpeg")]
+        let graph_source = (tid.clone(), slot.clone());
+        let id = tid.clone();
+        let keyframe_request = keyframe_request.clone();
+        let orientation = video_orientation.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = codec::decode(id, receiver, slot, keyframe_request, orientation) {
+                log::error!("ff::decode error: {}", e);
+            }
+        });
+        tokio::task::spawn_blocking(move || {
+            #[cfg(feature = "ffmpeg")]
+            if ffmpeg_graph {
+                let (id, slot) = graph_source;
+                let result =
+                    GraphCompositor::new(compositor.canvas().clone()).and_then(|mut graph| {
+                        graph.add_source(id, slot);
+                        codec::encode(graph, updates, h264_sender, rtc_sender)
+                    });
+                if let Err(e) = result {
+                    log::error!("codec::encode error: {}", e);
+                }
+                return;
+            }
+            if let Err(e) = codec::encode(compositor, updates, h264_sender, rtc_sender) {
+                log::error!("codec::encode error: {}", e);
+            }
+        });
+    }
+
+    // H265 无法解码，直接转封装为 Enhanced RTMP
+    let (h265_sender, mut h265_receiver) = unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(h265) = h265_receiver.recv().await {
+            if video_sender.send(VideoPacket::Hevc(h265)).is_err() {
+                log::error!("Rtmp client closed while sending h265 data");
+                break;
+            }
         }
     });
 
-    let _pc = rtc::init(sender, host, port, tid).await?;
+    let senders = TrackSenders {
+        h264: sender,
+        h265: Some(h265_sender),
+        audio: audio_sender,
+        speaker,
+        keyframe_request,
+        video_orientation,
+    };
+    let _pc = rtc::init(senders, host, port, tid).await?;
 
     tokio::signal::ctrl_c().await?;
 
+    // 结束推流，释放服务端的 WHIP 资源
+    if let Some(publisher) = publisher {
+        publisher.close().await;
+    }
+
     Ok(())
 }
@@ -1,7 +1,11 @@
+use crate::filter::FilterRegistry;
+use crate::yuv::rgb_to_yuv;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// 视频处理方式
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub enum FilterMode {
     /// 遮幅，可能有黑边
     Fit,
@@ -11,10 +15,13 @@ pub enum FilterMode {
 
     /// 拉伸，拉伸到设定比例
     Scale,
+
+    /// 同 Fit，但以模糊放大的同一画面填充黑边
+    BlurFill,
 }
 
 /// 视频位置
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct VideoPosition {
     /// 层
     pub layer: u32,
@@ -36,4 +43,1151 @@ pub struct VideoPosition {
 
     /// ID
     pub id: String,
+
+    /// 源缺失时的处理
+    #[serde(default)]
+    pub fallback: Fallback,
+
+    /// 不透明度，0.0 ~ 1.0
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+
+    /// 形状遮罩，遮罩外透明
+    #[serde(default)]
+    pub mask: Option<Mask>,
+
+    /// 源画面中的取景区域，不设置则为整个画面
+    #[serde(default)]
+    pub source: Option<Region>,
+
+    /// 在取景区域内放大的倍数，不小于 1.0
+    #[serde(default = "default_zoom")]
+    pub zoom: f32,
+
+    /// 放大后水平平移，-1.0 为最左，1.0 为最右
+    #[serde(default)]
+    pub pan_x: f32,
+
+    /// 放大后垂直平移，-1.0 为最上，1.0 为最下
+    #[serde(default)]
+    pub pan_y: f32,
+
+    /// 抠像，去除绿幕等背景后与下层混合
+    #[serde(default)]
+    pub chroma_key: Option<ChromaKey>,
+
+    /// 顺时针旋转角度，在源自带的视频方向之后应用
+    #[serde(default)]
+    pub rotation: Rotation,
+
+    /// 水平镜像
+    #[serde(default)]
+    pub flip_horizontal: bool,
+
+    /// 垂直翻转
+    #[serde(default)]
+    pub flip_vertical: bool,
+
+    /// 亮度、对比度、饱和度及 gamma 调整
+    #[serde(default)]
+    pub color_adjustment: Option<ColorAdjustment>,
+
+    /// 滤镜链，在内置处理之后按顺序应用
+    #[serde(default)]
+    pub filters: Vec<FilterConfig>,
+}
+
+impl VideoPosition {
+    /// 第 0 层、Fit 方式、其余参数取默认值的位置
+    pub fn new(id: impl Into<String>, x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            layer: 0,
+            x,
+            y,
+            width,
+            height,
+            mode: FilterMode::Fit,
+            id: id.into(),
+            fallback: Fallback::default(),
+            opacity: default_opacity(),
+            mask: None,
+            source: None,
+            zoom: default_zoom(),
+            pan_x: 0.0,
+            pan_y: 0.0,
+            chroma_key: None,
+            rotation: Rotation::None,
+            flip_horizontal: false,
+            flip_vertical: false,
+            color_adjustment: None,
+            filters: Vec::new(),
+        }
+    }
+
+    /// 尺寸不超过画布，滤镜需已在 filters 中注册且参数有效
+    pub fn validate(&self, canvas: &Canvas, filters: &FilterRegistry) -> anyhow::Result<()> {
+        let check = || {
+            anyhow::ensure!(
+                (1..=canvas.width).contains(&self.width)
+                    && (1..=canvas.height).contains(&self.height),
+                "Size must be within 1x1 ~ {}x{}: {}x{}",
+                canvas.width,
+                canvas.height,
+                self.width,
+                self.height
+            );
+            self.fallback.validate()?;
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&self.opacity),
+                "Opacity must be within 0 ~ 1: {}",
+                self.opacity
+            );
+            anyhow::ensure!(
+                (1.0..=MAX_ZOOM).contains(&self.zoom),
+                "Zoom must be within 1 ~ {}: {}",
+                MAX_ZOOM,
+                self.zoom
+            );
+            anyhow::ensure!(
+                (-1.0..=1.0).contains(&self.pan_x) && (-1.0..=1.0).contains(&self.pan_y),
+                "Pan must be within -1 ~ 1: ({}, {})",
+                self.pan_x,
+                self.pan_y
+            );
+            if let Some(region) = &self.source {
+                region.validate()?;
+            }
+            if let Some(key) = &self.chroma_key {
+                key.validate()?;
+            }
+            if let Some(adjustment) = &self.color_adjustment {
+                adjustment.validate()?;
+            }
+            for filter in &self.filters {
+                filters.build(filter)?;
+            }
+            Ok(())
+        };
+        check().map_err(|e| anyhow::anyhow!("Invalid video {}: {}", self.id, e))
+    }
+}
+
+/// 取景区域放大倍数的上限
+pub const MAX_ZOOM: f32 = 16.0;
+
+/// 顺时针旋转角度，JSON 中为 0、90、180 或 270
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    fn quarters(self) -> u8 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Clockwise270 => 3,
+        }
+    }
+
+    /// 按顺时针 90 度的次数构造
+    pub fn from_quarters(quarters: u8) -> Rotation {
+        match quarters % 4 {
+            0 => Rotation::None,
+            1 => Rotation::Clockwise90,
+            2 => Rotation::Clockwise180,
+            _ => Rotation::Clockwise270,
+        }
+    }
+}
+
+impl TryFrom<u32> for Rotation {
+    type Error = anyhow::Error;
+
+    fn try_from(degrees: u32) -> Result<Self, Self::Error> {
+        match degrees {
+            0 | 90 | 180 | 270 => Ok(Rotation::from_quarters((degrees / 90) as u8)),
+            _ => anyhow::bail!("Rotation must be 0, 90, 180 or 270: {}", degrees),
+        }
+    }
+}
+
+impl From<Rotation> for u32 {
+    fn from(rotation: Rotation) -> Self {
+        rotation.quarters() as u32 * 90
+    }
+}
+
+/// 抠像参数，取值与 OBS 的色度键一致
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChromaKey {
+    /// 关键色
+    pub color: Color,
+
+    /// 色度距离小于该值的像素完全透明，0.0 ~ 1.0
+    pub similarity: f32,
+
+    /// 透明到不透明的过渡范围，越大边缘越柔和
+    pub smoothness: f32,
+
+    /// 溢色去除范围，边缘处关键色的色度被去饱和
+    pub spill: f32,
+}
+
+impl ChromaKey {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let values = [
+            ("similarity", self.similarity),
+            ("smoothness", self.smoothness),
+            ("spill", self.spill),
+        ];
+        for (name, value) in values {
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&value),
+                "Chroma key {} must be within 0 ~ 1: {}",
+                name,
+                value
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Default for ChromaKey {
+    fn default() -> Self {
+        Self {
+            color: Color {
+                r: 0,
+                g: 0xff,
+                b: 0,
+            },
+            similarity: 0.4,
+            smoothness: 0.08,
+            spill: 0.1,
+        }
+    }
+}
+
+/// 滤镜链中的一个滤镜
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FilterConfig {
+    /// 注册的滤镜名称
+    pub name: String,
+
+    /// 滤镜参数，由滤镜自行解析
+    #[serde(default)]
+    pub config: serde_json::Value,
+}
+
+/// 画面色彩调整，默认值为不调整
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ColorAdjustment {
+    /// 亮度偏移，-1.0 ~ 1.0
+    pub brightness: f32,
+
+    /// 对比度，以中灰为中心缩放，1.0 为不变，0.0 ~ 4.0
+    pub contrast: f32,
+
+    /// 饱和度，0.0 为灰度，1.0 为不变，0.0 ~ 4.0
+    pub saturation: f32,
+
+    /// gamma，大于 1.0 时提亮暗部，0.1 ~ 10.0
+    pub gamma: f32,
+}
+
+impl Default for ColorAdjustment {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl ColorAdjustment {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let ranges = [
+            ("brightness", self.brightness, -1.0..=1.0),
+            ("contrast", self.contrast, 0.0..=4.0),
+            ("saturation", self.saturation, 0.0..=4.0),
+            ("gamma", self.gamma, 0.1..=10.0),
+        ];
+        for (name, value, range) in ranges {
+            anyhow::ensure!(
+                range.contains(&value),
+                "Color {} must be within {} ~ {}: {}",
+                name,
+                range.start(),
+                range.end(),
+                value
+            );
+        }
+        Ok(())
+    }
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_zoom() -> f32 {
+    1.0
+}
+
+/// 源画面中的矩形区域
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Region {
+    /// 相对源画面宽高的比例，0.0 ~ 1.0
+    Normalized {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+
+    /// 像素坐标
+    Pixel {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Region {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match *self {
+            Region::Normalized {
+                x,
+                y,
+                width,
+                height,
+            } => anyhow::ensure!(
+                (0.0..1.0).contains(&x)
+                    && (0.0..1.0).contains(&y)
+                    && width > 0.0
+                    && width <= 1.0 - x
+                    && height > 0.0
+                    && height <= 1.0 - y,
+                "Normalized region must be non-empty and within the frame: {:?}",
+                self
+            ),
+            Region::Pixel { width, height, .. } => anyhow::ensure!(
+                width > 0 && height > 0,
+                "Pixel region must be non-empty: {:?}",
+                self
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// 画面布局，可通过控制接口在运行时修改
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Layout {
+    /// 自动布局模板，设置后按当前活跃的源生成视频 tile，videos 中的 tile 绘制在其后
+    pub template: Option<Template>,
+
+    /// 视频 tile
+    pub videos: Vec<VideoPosition>,
+
+    /// 图片叠加，与视频一起按 layer 排序
+    pub images: Vec<ImageOverlay>,
+
+    /// 文字叠加，绘制在所有视频之上
+    pub texts: Vec<TextOverlay>,
+
+    /// 当前发言人 tile 的高亮边框
+    pub speaker_border: Option<Border>,
+
+    /// 切换到本布局时的过渡动画
+    pub transition: Transition,
+}
+
+impl Layout {
+    pub fn validate(&self, canvas: &Canvas, filters: &FilterRegistry) -> anyhow::Result<()> {
+        if let Some(template) = &self.template {
+            template.validate(canvas)?;
+        }
+        for video in &self.videos {
+            video.validate(canvas, filters)?;
+        }
+        for image in &self.images {
+            image.validate(canvas)?;
+        }
+        for text in &self.texts {
+            text.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// 布局切换的过渡动画：tile 的位置、尺寸及不透明度渐变，新出现的 tile 淡入，移除的淡出
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Transition {
+    /// 时长（秒），为 0 时立即切换
+    pub duration_secs: f64,
+
+    /// 缓动曲线
+    pub easing: Easing,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            duration_secs: 0.0,
+            easing: Easing::EaseInOut,
+        }
+    }
+}
+
+/// 缓动曲线
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// 将 0.0 ~ 1.0 的时间进度映射为动画进度，使用三次曲线
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// tile 边框
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Border {
+    pub color: Color,
+
+    /// 宽度，绘制在 tile 内侧
+    #[serde(default = "default_border_width")]
+    pub width: u32,
+}
+
+fn default_border_width() -> u32 {
+    4
+}
+
+/// 图片叠加，如台标、角标、边框，PNG 可带透明通道
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ImageOverlay {
+    /// ID，用于运行时修改
+    pub id: String,
+
+    /// 图片文件
+    pub path: PathBuf,
+
+    /// 层，与视频同层时绘制在视频之上
+    #[serde(default)]
+    pub layer: u32,
+
+    /// 横坐标
+    pub x: u32,
+
+    /// 纵坐标
+    pub y: u32,
+
+    /// 宽，不设置则按高等比缩放，都不设置则为原尺寸
+    #[serde(default)]
+    pub width: Option<u32>,
+
+    /// 高，不设置则按宽等比缩放
+    #[serde(default)]
+    pub height: Option<u32>,
+
+    /// 不透明度，0.0 ~ 1.0
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+}
+
+impl ImageOverlay {
+    /// 尺寸不超过画布，未设置的边按图片比例计算，读取时再检查
+    pub fn validate(&self, canvas: &Canvas) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.width
+                .is_none_or(|width| (1..=canvas.width).contains(&width))
+                && self
+                    .height
+                    .is_none_or(|height| (1..=canvas.height).contains(&height)),
+            "Image {} size must be within 1x1 ~ {}x{}: {:?}x{:?}",
+            self.id,
+            canvas.width,
+            canvas.height,
+            self.width,
+            self.height
+        );
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&self.opacity),
+            "Image {} opacity must be within 0 ~ 1: {}",
+            self.id,
+            self.opacity
+        );
+        Ok(())
+    }
+}
+
+/// 自动布局模板，根据画布尺寸和活跃的源计算各 tile 的位置
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Template {
+    /// 模板类型
+    #[serde(rename = "type")]
+    pub kind: TemplateKind,
+
+    /// 参与布局的源及其顺序，为空则为所有源按 ID 排序
+    #[serde(default)]
+    pub sources: Vec<String>,
+
+    /// 主画面的源，不设置或不活跃时取第一个源
+    #[serde(default)]
+    pub main: Option<String>,
+
+    /// 以当前发言人作为主画面，优先于 main
+    #[serde(default)]
+    pub follow_speaker: bool,
+
+    /// 画布四周的留白
+    #[serde(default)]
+    pub padding: u32,
+
+    /// tile 之间的间距
+    #[serde(default)]
+    pub gap: u32,
+
+    /// tile 的宽高比，不设置则铺满分到的区域，范围为 [`MIN_ASPECT`] ~ [`MAX_ASPECT`]
+    #[serde(default)]
+    pub aspect: Option<f32>,
+
+    /// 视频处理方式
+    #[serde(default = "default_template_mode")]
+    pub mode: FilterMode,
+
+    /// 源中断超过该时长（秒）视为离开，从布局中移除
+    #[serde(default = "default_leave_secs")]
+    pub leave_secs: f64,
+}
+
+/// 模板 tile 宽高比的下限
+pub const MIN_ASPECT: f32 = 0.1;
+/// 模板 tile 宽高比的上限
+pub const MAX_ASPECT: f32 = 10.0;
+/// 源离开时长的上限（秒）
+pub const MAX_LEAVE_SECS: f64 = 3600.0;
+
+impl Template {
+    /// 留白及间距需小于画布的短边
+    pub fn validate(&self, canvas: &Canvas) -> anyhow::Result<()> {
+        if let Some(aspect) = self.aspect {
+            anyhow::ensure!(
+                (MIN_ASPECT..=MAX_ASPECT).contains(&aspect),
+                "Template aspect must be within {} ~ {}: {}",
+                MIN_ASPECT,
+                MAX_ASPECT,
+                aspect
+            );
+        }
+        anyhow::ensure!(
+            (0.0..=MAX_LEAVE_SECS).contains(&self.leave_secs),
+            "Template leave secs must be within 0 ~ {}: {}",
+            MAX_LEAVE_SECS,
+            self.leave_secs
+        );
+        let size = canvas.width.min(canvas.height);
+        anyhow::ensure!(
+            self.padding.saturating_mul(2) < size,
+            "Template padding must be less than half of the canvas size {}: {}",
+            size,
+            self.padding
+        );
+        anyhow::ensure!(
+            self.gap < size,
+            "Template gap must be less than the canvas size {}: {}",
+            size,
+            self.gap
+        );
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn new(kind: TemplateKind) -> Self {
+        Self {
+            kind,
+            sources: Vec::new(),
+            main: None,
+            follow_speaker: false,
+            padding: 0,
+            gap: 0,
+            aspect: None,
+            mode: default_template_mode(),
+            leave_secs: default_leave_secs(),
+        }
+    }
+}
+
+fn default_template_mode() -> FilterMode {
+    FilterMode::Crop
+}
+
+fn default_leave_secs() -> f64 {
+    5.0
+}
+
+/// 布局模板类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    /// 宫格
+    Grid,
+
+    /// 主画面在上，其余源在下方排成一行
+    SpeakerStrip,
+
+    /// 所有源水平并排
+    SideBySide,
+
+    /// 主画面铺满，其余源为右下角的小窗
+    PictureInPicture,
+
+    /// 所有源垂直排列
+    VerticalStack,
+}
+
+/// 文字叠加，如姓名条、标题、滚动字幕
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TextOverlay {
+    /// ID，用于运行时修改
+    pub id: String,
+
+    /// 单行文字
+    pub text: String,
+
+    /// 字体文件
+    pub font: PathBuf,
+
+    /// 字号（像素）
+    #[serde(default = "default_font_size")]
+    pub size: f32,
+
+    /// 文字颜色
+    #[serde(default = "Color::white")]
+    pub color: Color,
+
+    /// 背景框颜色，不设置则无背景框
+    #[serde(default)]
+    pub background: Option<Color>,
+
+    /// 背景框内边距
+    #[serde(default = "default_padding")]
+    pub padding: u32,
+
+    /// 所属视频 tile 的 ID，不设置则相对整个画布定位
+    #[serde(default)]
+    pub attach: Option<String>,
+
+    /// 对齐位置
+    #[serde(default)]
+    pub anchor: Anchor,
+
+    /// 对齐后的水平偏移
+    #[serde(default)]
+    pub x: i32,
+
+    /// 对齐后的垂直偏移
+    #[serde(default)]
+    pub y: i32,
+
+    /// 滚动速度（像素/秒），大于 0 时为滚动字幕，在所属区域内从右向左循环滚动，忽略水平对齐及偏移
+    #[serde(default)]
+    pub scroll_speed: f32,
+}
+
+/// 文字字号上限（像素）
+pub const MAX_TEXT_SIZE: f32 = 512.0;
+
+impl TextOverlay {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (1.0..=MAX_TEXT_SIZE).contains(&self.size),
+            "Text {} size must be within 1 ~ {}: {}",
+            self.id,
+            MAX_TEXT_SIZE,
+            self.size
+        );
+        Ok(())
+    }
+}
+
+fn default_font_size() -> f32 {
+    24.0
+}
+
+fn default_padding() -> u32 {
+    4
+}
+
+/// 叠加层在所属区域内的对齐位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    #[default]
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// tile 的形状遮罩
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Mask {
+    /// 矩形
+    Rectangle,
+
+    /// 圆角矩形
+    RoundedRectangle { radius: u32 },
+
+    /// 居中的圆形，直径为 tile 的短边
+    Circle,
+}
+
+/// 源尚未出帧或中断时的处理
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Fallback {
+    /// 中断后保持最后一帧的时长（秒）
+    pub hold_secs: f64,
+
+    /// 超时后显示的占位画面
+    pub placeholder: Placeholder,
+}
+
+/// 保持最后一帧时长的上限（秒）
+pub const MAX_HOLD_SECS: f64 = 3600.0;
+
+impl Fallback {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (0.0..=MAX_HOLD_SECS).contains(&self.hold_secs),
+            "Fallback hold secs must be within 0 ~ {}: {}",
+            MAX_HOLD_SECS,
+            self.hold_secs
+        );
+        Ok(())
+    }
+}
+
+impl Default for Fallback {
+    fn default() -> Self {
+        Self {
+            hold_secs: 5.0,
+            placeholder: Placeholder::Color {
+                color: Color {
+                    r: 0x20,
+                    g: 0x20,
+                    b: 0x20,
+                },
+            },
+        }
+    }
+}
+
+/// 占位画面
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Placeholder {
+    /// 纯色
+    Color { color: Color },
+
+    /// 图片，按 Fit 方式缩放
+    Image { path: PathBuf },
+
+    /// 文字，如“重新连接中”，居中显示
+    Text {
+        text: String,
+
+        /// 字体文件
+        font: PathBuf,
+
+        /// 文字颜色
+        #[serde(default = "Color::white")]
+        color: Color,
+
+        /// 背景色
+        #[serde(default)]
+        background: Color,
+    },
+}
+
+/// 输出画布
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Canvas {
+    /// 宽
+    pub width: u32,
+
+    /// 高
+    pub height: u32,
+
+    /// 帧率
+    pub fps: u32,
+
+    /// 背景色
+    pub background: Color,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            fps: 25,
+            background: Color::default(),
+        }
+    }
+}
+
+impl Canvas {
+    /// 画布尺寸需为偶数以满足 4:2:0 采样
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.width > 0
+                && self.height > 0
+                && self.width.is_multiple_of(2)
+                && self.height.is_multiple_of(2),
+            "Canvas size must be positive and even: {}x{}",
+            self.width,
+            self.height
+        );
+        anyhow::ensure!(self.fps > 0, "Canvas fps must be positive");
+        Ok(())
+    }
+}
+
+/// RGB 颜色，字符串形式为 #RRGGBB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub fn white() -> Self {
+        Self {
+            r: 0xff,
+            g: 0xff,
+            b: 0xff,
+        }
+    }
+
+    /// 转换为 BT.601 有限范围的 YUV
+    pub fn to_yuv(self) -> (u8, u8, u8) {
+        rgb_to_yuv(self.r, self.g, self.b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        anyhow::ensure!(
+            hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            "Invalid color: {}",
+            s
+        );
+        let value = u32::from_str_radix(hex, 16)?;
+        Ok(Self {
+            r: (value >> 16) as u8,
+            g: (value >> 8) as u8,
+            b: value as u8,
+        })
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Color> for String {
+    fn from(c: Color) -> Self {
+        format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+    }
+}
+
+/// 码率控制模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateControl {
+    /// 质量优先
+    Quality,
+
+    /// 恒定码率
+    #[default]
+    Bitrate,
+
+    /// 仅根据缓冲区状态调整质量
+    Buffer,
+
+    /// 根据时间戳控制码率
+    Timestamp,
+
+    /// 关闭码率控制
+    Off,
+}
+
+impl FromStr for RateControl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "quality" => Ok(Self::Quality),
+            "bitrate" | "cbr" => Ok(Self::Bitrate),
+            "buffer" => Ok(Self::Buffer),
+            "timestamp" => Ok(Self::Timestamp),
+            "off" => Ok(Self::Off),
+            _ => anyhow::bail!("Invalid rate control mode: {}", s),
+        }
+    }
+}
+
+/// 编码参数
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EncoderSettings {
+    /// 目标码率（bps）
+    pub bitrate: u32,
+
+    /// 最大码率（bps），不设置则不限制
+    pub max_bitrate: Option<u32>,
+
+    /// 关键帧间隔（帧），0 表示仅首帧为关键帧
+    pub keyframe_interval: u32,
+
+    /// 码率控制模式
+    pub rate_control: RateControl,
+
+    /// 码率超限时允许跳帧
+    pub skip_frame: bool,
+
+    /// 编码线程数，0 为自动
+    pub threads: u16,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            bitrate: 2_000_000,
+            max_bitrate: None,
+            keyframe_interval: 50,
+            rate_control: RateControl::default(),
+            skip_frame: true,
+            threads: 1,
+        }
+    }
+}
+
+impl EncoderSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.bitrate > 0, "Encoder bitrate must be positive");
+        if let Some(max_bitrate) = self.max_bitrate {
+            anyhow::ensure!(
+                max_bitrate >= self.bitrate,
+                "Encoder max bitrate {} is less than bitrate {}",
+                max_bitrate,
+                self.bitrate
+            );
+        }
+        Ok(())
+    }
+}
+
+/// 任务配置文件（JSON），命令行参数优先
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct JobConfig {
+    /// 输出画布
+    pub canvas: Canvas,
+
+    /// 编码参数
+    pub encoder: EncoderSettings,
+
+    /// 源缺失时的默认处理
+    pub fallback: Fallback,
+
+    /// 初始布局，为空时单路源铺满画布
+    pub layout: Layout,
+
+    /// 发言人检测
+    pub speaker: SpeakerDetection,
+}
+
+/// 发言人检测参数
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpeakerDetection {
+    /// 语音电平阈值（dBov），高于该值视为在说话
+    pub threshold: f32,
+
+    /// 电平持续高于阈值该时长（秒）后判定为开始说话
+    pub attack_secs: f64,
+
+    /// 电平持续低于阈值该时长（秒）后判定为停止说话
+    pub release_secs: f64,
+
+    /// 其他源持续比当前发言人更响该时长（秒）后切换发言人
+    pub switch_secs: f64,
+}
+
+impl Default for SpeakerDetection {
+    fn default() -> Self {
+        Self {
+            threshold: -50.0,
+            attack_secs: 0.3,
+            release_secs: 1.0,
+            switch_secs: 1.5,
+        }
+    }
+}
+
+impl JobConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_video_position() {
+        let canvas = Canvas::default();
+        let filters = FilterRegistry::default();
+        let video = VideoPosition::new("a", 0, 0, 640, 360);
+        assert!(video.validate(&canvas, &filters).is_ok());
+
+        let invalid: [fn(&mut VideoPosition); 9] = [
+            |v| v.width = 0,
+            |v| v.height = 100_000,
+            |v| v.opacity = 1.5,
+            |v| v.zoom = 0.0,
+            |v| v.pan_x = f32::NAN,
+            |v| {
+                v.source = Some(Region::Normalized {
+                    x: 0.5,
+                    y: 0.0,
+                    width: 0.0,
+                    height: 1.0,
+                })
+            },
+            |v| {
+                v.chroma_key = Some(ChromaKey {
+                    similarity: -0.1,
+                    ..Default::default()
+                })
+            },
+            |v| v.fallback.hold_secs = f64::INFINITY,
+            |v| {
+                v.filters.push(FilterConfig {
+                    name: "missing".to_string(),
+                    config: Default::default(),
+                })
+            },
+        ];
+        for modify in invalid {
+            let mut video = video.clone();
+            modify(&mut video);
+            assert!(video.validate(&canvas, &filters).is_err(), "{:?}", video);
+        }
+    }
+
+    #[test]
+    fn validate_image_overlay() {
+        let canvas = Canvas::default();
+        let mut image = ImageOverlay {
+            id: "logo".to_string(),
+            path: PathBuf::from("logo.png"),
+            layer: 0,
+            x: 0,
+            y: 0,
+            width: Some(1280),
+            height: None,
+            opacity: 1.0,
+        };
+        assert!(image.validate(&canvas).is_ok());
+        image.height = Some(100_000);
+        assert!(image.validate(&canvas).is_err());
+        image.height = Some(0);
+        assert!(image.validate(&canvas).is_err());
+        image.height = None;
+        image.opacity = -0.5;
+        assert!(image.validate(&canvas).is_err());
+    }
+
+    #[test]
+    fn reject_out_of_range_color_adjustment() {
+        assert!(ColorAdjustment::default().validate().is_ok());
+        let invalid = [
+            ColorAdjustment {
+                contrast: -1.0,
+                ..Default::default()
+            },
+            ColorAdjustment {
+                gamma: 0.0,
+                ..Default::default()
+            },
+            ColorAdjustment {
+                brightness: f32::NAN,
+                ..Default::default()
+            },
+        ];
+        for adjustment in invalid {
+            assert!(adjustment.validate().is_err(), "{:?}", adjustment);
+        }
+    }
 }
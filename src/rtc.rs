@@ -1,15 +1,22 @@
 #![allow(dead_code)]
 
-use crate::api::ApiClient;
+use crate::api::{ApiClient, PublishParam, WhipClient, WhipSession};
 use crate::h264::AVCDecoderConfigurationRecord;
+use crate::h265::{H265Data, H265Depacketizer, HEVCDecoderConfigurationRecord};
+use crate::speaker::SpeakerDetector;
 use crate::{H264Data, PlayParam};
+use bytes::{BufMut, BytesMut};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264, MIME_TYPE_OPUS};
-use webrtc::api::APIBuilder;
+use webrtc::api::{APIBuilder, API};
 use webrtc::interceptor::registry::Registry;
+use webrtc::media::Sample;
 use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
@@ -17,58 +24,58 @@ use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
 use webrtc::rtp::codecs::h264::H264Packet;
+use webrtc::rtp::extension::audio_level_extension::AudioLevelExtension;
+use webrtc::rtp::packet::Packet;
 use webrtc::rtp::packetizer::Depacketizer;
 use webrtc::rtp_transceiver::rtp_codec::{
-    RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType,
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTCRtpHeaderExtensionCapability, RTPCodecType,
 };
 use webrtc::rtp_transceiver::rtp_receiver::RTCRtpReceiver;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
 use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
 use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+use webrtc::sdp::extmap::AUDIO_LEVEL_URI;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocalWriter;
 use webrtc::track::track_remote::TrackRemote;
+use webrtc::util::Unmarshal;
+
+/// 拉流得到的音视频数据的去向
+#[derive(Clone)]
+pub struct TrackSenders {
+    /// H264 视频，送往解码器
+    pub h264: Sender<H264Data>,
+
+    /// H265 视频，没有可用的解码器，只能直接转封装
+    pub h265: Option<UnboundedSender<H265Data>>,
+
+    /// Opus 音频 RTP 包
+    pub audio: Option<UnboundedSender<Packet>>,
+
+    /// 按音频包的 ssrc-audio-level 头扩展检测发言人
+    pub speaker: SpeakerDetector,
+
+    /// 解码出错时通知立即发送 PLI 请求关键帧
+    pub keyframe_request: Arc<Notify>,
+
+    /// 视频包 video-orientation 头扩展（CVO）的最新值，解码后据此旋转画面
+    pub video_orientation: Arc<AtomicU8>,
+}
+
+const MIME_TYPE_H265: &str = "video/H265";
+
+/// 3GPP 视频方向头扩展（CVO），移动端推流以此标记画面旋转
+pub const VIDEO_ORIENTATION_URI: &str = "urn:3gpp:video-orientation";
 
 pub async fn init(
-    sender: Sender<H264Data>,
+    senders: TrackSenders,
     host: String,
     port: u16,
     tid: String,
 ) -> anyhow::Result<Arc<RTCPeerConnection>> {
-    let mut me = MediaEngine::default();
-    me.register_codec(
-        RTCRtpCodecParameters {
-            capability: RTCRtpCodecCapability {
-                mime_type: MIME_TYPE_H264.to_owned(),
-                clock_rate: 90000,
-                channels: 0,
-                sdp_fmtp_line: "".to_owned(),
-                rtcp_feedback: vec![],
-            },
-            payload_type: 102,
-            ..Default::default()
-        },
-        RTPCodecType::Video,
-    )?;
-    me.register_codec(
-        RTCRtpCodecParameters {
-            capability: RTCRtpCodecCapability {
-                mime_type: MIME_TYPE_OPUS.to_owned(),
-                ..Default::default()
-            },
-            payload_type: 120,
-            ..Default::default()
-        },
-        RTPCodecType::Audio,
-    )?;
-
-    let mut registry = Registry::new();
-
-    // Use the default set of Interceptors
-    registry = register_default_interceptors(registry, &mut me)?;
-
-    // Create the API object with the MediaEngine
-    let api = APIBuilder::new()
-        .with_media_engine(me)
-        .with_interceptor_registry(registry)
-        .build();
+    let api = new_api()?;
+    let source_id = tid.clone();
 
     // Prepare the configuration
     let config = RTCConfiguration::default();
@@ -109,9 +116,14 @@ pub async fn init(
         tid,
     };
     let play = client.play(&param).await?;
+    log::info!(
+        "play session: {}, server: {:?}",
+        play.session_id,
+        play.server
+    );
     log::info!("remote description:\n {}", play.sdp);
 
-    const PROFILE_PREFIX: &'static str = "profile-level-id=";
+    const PROFILE_PREFIX: &str = "profile-level-id=";
     let profile_level_id = match play.sdp.find(PROFILE_PREFIX) {
         None => anyhow::bail!("Failed to get profile-level-id"),
         Some(index) => {
@@ -131,14 +143,34 @@ pub async fn init(
     peer_connection
         .on_track(Box::new(
             move |track: Option<Arc<TrackRemote>>, receiver: Option<Arc<RTCRtpReceiver>>| {
-                let s = sender.clone();
+                let senders = senders.clone();
+                let source_id = source_id.clone();
                 let pc = pc.clone();
                 let r = record.clone();
                 Box::pin(async move {
+                    // 协商得到的音频电平及视频方向头扩展 ID
+                    let header_extensions = match &receiver {
+                        Some(receiver) => receiver.get_parameters().await.header_extensions,
+                        None => Vec::new(),
+                    };
+                    let extension_id = |uri: &str| {
+                        header_extensions
+                            .iter()
+                            .find(|extension| extension.uri == uri)
+                            .map(|extension| extension.id as u8)
+                    };
+                    let audio_level_id = extension_id(AUDIO_LEVEL_URI);
+                    let orientation_id = extension_id(VIDEO_ORIENTATION_URI);
                     if let Some(track) = track {
-                        let s = s.clone();
+                        let s = senders.h264.clone();
+                        let speaker = senders.speaker.clone();
+                        let source_id = source_id.clone();
+                        let h265 = senders.h265.clone();
+                        let mut a = senders.audio.clone();
                         let pc = pc.clone();
                         let r = r.clone();
+                        let keyframe_request = senders.keyframe_request.clone();
+                        let video_orientation = senders.video_orientation.clone();
                         tokio::spawn(async move {
                             let codec = track.codec().await;
                             let mime_type = codec.capability.mime_type;
@@ -177,12 +209,31 @@ pub async fn init(
                                             }
                                         }
 
-                                        tokio::time::sleep(Duration::from_secs(3)).await;
+                                        tokio::select! {
+                                            _ = tokio::time::sleep(Duration::from_secs(3)) => {}
+                                            _ = keyframe_request.notified() => {
+                                                log::info!("Keyframe requested by decoder");
+                                            }
+                                        }
                                     }
                                 });
+                                if mime_type.eq_ignore_ascii_case(MIME_TYPE_H265) {
+                                    read_h265(track, h265).await;
+                                    return;
+                                }
                                 let mut rtp_decoder = H264Packet::default();
+                                let mut rtp_clock = RtpClock::new(clock_rate);
                                 let mut has_key_frame = false;
                                 while let Ok((packet, _attr)) = track.read_rtp().await {
+                                    // CVO 一般只在关键帧及方向变化后的帧携带，保留最新值
+                                    if let Some(cvo) = orientation_id
+                                        .and_then(|id| packet.header.get_extension(id))
+                                        .and_then(|raw| raw.first().copied())
+                                    {
+                                        if video_orientation.swap(cvo, Ordering::Relaxed) != cvo {
+                                            log::info!("Video orientation changed: {:#04x}", cvo);
+                                        }
+                                    }
                                     if !has_key_frame {
                                         has_key_frame = is_key_frame(&packet.payload);
                                         if !has_key_frame {
@@ -262,9 +313,11 @@ pub async fn init(
                                         match rtp_decoder.depacketize(&packet.payload) {
                                             Ok(h264_pkt) => {
                                                 if !h264_pkt.is_empty() {
+                                                    // WebRTC 中没有 B 帧，RTP 时间戳即为 pts
                                                     let timestamp =
-                                                        packet.header.timestamp / clock_rate;
-                                                    if s.send(H264Data::data(timestamp, h264_pkt))
+                                                        rtp_clock.millis(packet.header.timestamp);
+                                                    let marker = packet.header.marker;
+                                                    if s.send(H264Data::nal(timestamp, timestamp, h264_pkt, marker))
                                                         .await
                                                         .is_err()
                                                     {
@@ -286,13 +339,33 @@ pub async fn init(
                                     }
                                 }
                             } else {
-                                while let Ok((_packet, _)) = track.read_rtp().await {
+                                // 未协商音频电平头扩展时该源不参与发言人检测
+                                if audio_level_id.is_none() {
+                                    log::warn!(
+                                        "Source {} did not negotiate {}, speaker detection ignores it",
+                                        source_id,
+                                        AUDIO_LEVEL_URI
+                                    );
+                                }
+                                while let Ok((packet, _)) = track.read_rtp().await {
                                     // log::info!(
                                     //     "[{}:{}] {} bytes received.",
                                     //     ssrc,
                                     //     mime_type,
                                     //     packet.payload.len()
                                     // );
+                                    if let Some(level) = audio_level_id
+                                        .and_then(|id| packet.header.get_extension(id))
+                                        .and_then(|mut raw| AudioLevelExtension::unmarshal(&mut raw).ok())
+                                    {
+                                        speaker.update(&source_id, level.level, Instant::now());
+                                    }
+                                    if let Some(audio_sender) = &a {
+                                        if audio_sender.send(packet).is_err() {
+                                            log::warn!("Audio receiver closed, stop forwarding");
+                                            a = None;
+                                        }
+                                    }
                                 }
                             }
                         });
@@ -360,6 +433,388 @@ pub async fn init(
     Ok(peer_connection)
 }
 
+/// 创建 WebRTC API 对象，拉流与推流共用同一套编解码器及拦截器配置
+fn new_api() -> anyhow::Result<API> {
+    let mut me = MediaEngine::default();
+    me.register_codec(
+        RTCRtpCodecParameters {
+            capability: RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_H264.to_owned(),
+                clock_rate: 90000,
+                channels: 0,
+                sdp_fmtp_line: "".to_owned(),
+                rtcp_feedback: vec![],
+            },
+            payload_type: 102,
+            ..Default::default()
+        },
+        RTPCodecType::Video,
+    )?;
+    me.register_codec(
+        RTCRtpCodecParameters {
+            capability: RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_H265.to_owned(),
+                clock_rate: 90000,
+                channels: 0,
+                sdp_fmtp_line: "".to_owned(),
+                rtcp_feedback: vec![],
+            },
+            payload_type: 104,
+            ..Default::default()
+        },
+        RTPCodecType::Video,
+    )?;
+    me.register_codec(
+        RTCRtpCodecParameters {
+            capability: RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_OPUS.to_owned(),
+                ..Default::default()
+            },
+            payload_type: 120,
+            ..Default::default()
+        },
+        RTPCodecType::Audio,
+    )?;
+    me.register_header_extension(
+        RTCRtpHeaderExtensionCapability {
+            uri: AUDIO_LEVEL_URI.to_owned(),
+        },
+        RTPCodecType::Audio,
+        vec![],
+    )?;
+    me.register_header_extension(
+        RTCRtpHeaderExtensionCapability {
+            uri: VIDEO_ORIENTATION_URI.to_owned(),
+        },
+        RTPCodecType::Video,
+        vec![],
+    )?;
+
+    let mut registry = Registry::new();
+
+    // Use the default set of Interceptors
+    registry = register_default_interceptors(registry, &mut me)?;
+
+    // Create the API object with the MediaEngine
+    Ok(APIBuilder::new()
+        .with_media_engine(me)
+        .with_interceptor_registry(registry)
+        .build())
+}
+
+/// 将 RTP 时间戳换算为从第一个包开始的毫秒数，处理 32 位回绕及少量乱序
+struct RtpClock {
+    clock_rate: u32,
+    last: Option<u32>,
+    elapsed: i64,
+}
+
+impl RtpClock {
+    fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate: clock_rate.max(1),
+            last: None,
+            elapsed: 0,
+        }
+    }
+
+    fn millis(&mut self, timestamp: u32) -> u32 {
+        if let Some(last) = self.last {
+            self.elapsed += timestamp.wrapping_sub(last) as i32 as i64;
+        }
+        self.last = Some(timestamp);
+        (self.elapsed.max(0) * 1000 / self.clock_rate as i64) as u32
+    }
+}
+
+/// 读取 H265 视频轨道，按 RTP marker 组装成完整的访问单元后发送
+async fn read_h265(track: Arc<TrackRemote>, sender: Option<UnboundedSender<H265Data>>) {
+    let sender = match sender {
+        Some(sender) => sender,
+        None => {
+            log::warn!("No receiver for H265 video, ignore track {}", track.ssrc());
+            return;
+        }
+    };
+
+    let mut rtp_clock = RtpClock::new(track.codec().await.capability.clock_rate);
+    let mut depacketizer = H265Depacketizer::default();
+    let mut access_unit = BytesMut::new();
+    let mut configuration_sent = false;
+    while let Ok((packet, _attr)) = track.read_rtp().await {
+        match depacketizer.depacketize(&packet.payload) {
+            Ok(nal_units) => access_unit.put_slice(&nal_units),
+            Err(e) => {
+                log::error!("Failed to depacketize rtp packet to h265: {}", e);
+                access_unit.clear();
+                continue;
+            }
+        }
+
+        if !packet.header.marker || access_unit.is_empty() {
+            continue;
+        }
+        let data = access_unit.split().freeze();
+
+        if !configuration_sent {
+            match HEVCDecoderConfigurationRecord::from_annexb(&data) {
+                Ok(Some(record)) => {
+                    log::info!("HEVCDecoderConfigurationRecord: {:0x?}", record);
+                    if sender.send(H265Data::configuration(record)).is_err() {
+                        break;
+                    }
+                    configuration_sent = true;
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    log::error!("Invalid h265 parameter sets: {}", e);
+                    continue;
+                }
+            }
+        }
+
+        let timestamp = rtp_clock.millis(packet.header.timestamp);
+        if sender
+            .send(H265Data::data(timestamp, timestamp, data))
+            .is_err()
+        {
+            log::error!("Failed to send h265 packet");
+            break;
+        }
+    }
+}
+
+/// WebRTC 推流目标
+#[derive(Debug, Clone)]
+pub enum PublishTarget {
+    /// SRS 推流接口，格式为 webrtc://host[:port]/app/stream，端口为 API 端口
+    Srs {
+        host: String,
+        port: u16,
+        app: String,
+        stream: String,
+    },
+
+    /// WHIP 推流地址，格式为 http(s)://...
+    Whip { endpoint: String },
+}
+
+impl FromStr for PublishTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(Self::Whip {
+                endpoint: s.to_string(),
+            });
+        }
+
+        let rest = match s.strip_prefix("webrtc://") {
+            Some(rest) => rest,
+            None => anyhow::bail!("Unsupported publish url: {}", s),
+        };
+
+        let (authority, path) = match rest.split_once('/') {
+            Some(parts) => parts,
+            None => anyhow::bail!("Missing app and stream in publish url: {}", s),
+        };
+        let (app, stream) = match path.trim_end_matches('/').rsplit_once('/') {
+            Some((app, stream)) if !app.is_empty() && !stream.is_empty() => (app, stream),
+            _ => anyhow::bail!("Missing app or stream in publish url: {}", s),
+        };
+        // IPv6 地址带方括号，其中的冒号不是端口分隔符
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.ends_with(']') => match port.parse() {
+                Ok(port) => (host, port),
+                Err(_) => anyhow::bail!("Invalid port in publish url: {}", s),
+            },
+            _ => (authority, 443),
+        };
+        if host.is_empty() {
+            anyhow::bail!("Missing host in publish url: {}", s);
+        }
+
+        Ok(Self::Srs {
+            host: host.to_string(),
+            port,
+            app: app.to_string(),
+            stream: stream.to_string(),
+        })
+    }
+}
+
+/// WebRTC 推流会话，退出前调用 [`Publisher::close`] 释放服务端资源
+pub struct Publisher {
+    peer_connection: Arc<RTCPeerConnection>,
+    /// WHIP 推流时的客户端及资源，结束推流时删除
+    whip: Option<(WhipClient, WhipSession)>,
+}
+
+impl Publisher {
+    /// 删除 WHIP 资源并关闭连接
+    pub async fn close(self) {
+        if let Some((client, session)) = &self.whip {
+            match client.delete(session).await {
+                Ok(()) => log::info!("[publish] whip resource deleted: {:?}", session.location),
+                Err(e) => log::error!("[publish] Failed to delete whip resource: {}", e),
+            }
+        }
+        if let Err(e) = self.peer_connection.close().await {
+            log::error!("[publish] Failed to close peer connection: {}", e);
+        }
+    }
+}
+
+/// 将合成后的 H264 视频及 Opus 音频通过 WebRTC 推送到 SRS 或 WHIP 服务
+pub async fn publish(
+    target: PublishTarget,
+    token: Option<String>,
+    mut video_receiver: UnboundedReceiver<H264Data>,
+    mut audio_receiver: UnboundedReceiver<Packet>,
+) -> anyhow::Result<Publisher> {
+    let api = new_api()?;
+
+    let peer_connection = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await?);
+
+    let stream_id = env!("CARGO_PKG_NAME").to_string();
+    let video_track = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_owned(),
+            clock_rate: 90000,
+            channels: 0,
+            sdp_fmtp_line: "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f"
+                .to_owned(),
+            rtcp_feedback: vec![],
+        },
+        "video".to_owned(),
+        stream_id.clone(),
+    ));
+    let audio_track = Arc::new(TrackLocalStaticRTP::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_OPUS.to_owned(),
+            clock_rate: 48000,
+            channels: 2,
+            sdp_fmtp_line: "".to_owned(),
+            rtcp_feedback: vec![],
+        },
+        "audio".to_owned(),
+        stream_id,
+    ));
+
+    let video_sender = peer_connection.add_track(video_track.clone()).await?;
+    let audio_sender = peer_connection.add_track(audio_track.clone()).await?;
+    drain_rtcp(video_sender);
+    drain_rtcp(audio_sender);
+
+    peer_connection
+        .on_peer_connection_state_change(Box::new(move |state: RTCPeerConnectionState| {
+            Box::pin(async move {
+                log::info!("[publish][on_peer_connection_state_change] {}", state);
+            })
+        }))
+        .await;
+
+    let offer = peer_connection.create_offer(None).await?;
+    let mut gather_complete = peer_connection.gathering_complete_promise().await;
+    peer_connection.set_local_description(offer).await?;
+    let _ = gather_complete.recv().await;
+
+    let offer = match peer_connection.local_description().await {
+        Some(offer) => offer,
+        None => anyhow::bail!("Failed to get local description"),
+    };
+    log::info!("[publish] local description:\n {}", offer.sdp);
+
+    let mut whip = None;
+    let sdp = match target {
+        PublishTarget::Srs {
+            host,
+            port,
+            app,
+            stream,
+        } => {
+            let client = ApiClient::new(&host, port);
+            let param = PublishParam {
+                api: client.publish_api_url(),
+                client_ip: None,
+                sdp: offer.sdp,
+                stream_url: format!("webrtc://{}/{}/{}", host, app, stream),
+                tid: stream,
+            };
+            let result = client.publish(&param).await?;
+            log::info!(
+                "[publish] srs session: {}, server: {:?}",
+                result.session_id,
+                result.server
+            );
+            result.sdp
+        }
+        PublishTarget::Whip { endpoint } => {
+            let client = WhipClient::new(&endpoint, token);
+            let mut session = client.publish(offer.sdp).await?;
+            log::info!("[publish] whip resource: {:?}", session.location);
+            let sdp = std::mem::take(&mut session.sdp);
+            whip = Some((client, session));
+            sdp
+        }
+    };
+    log::info!("[publish] remote description:\n {}", sdp);
+
+    let mut answer = RTCSessionDescription::default();
+    answer.sdp_type = RTCSdpType::Answer;
+    answer.sdp = sdp;
+    peer_connection.set_remote_description(answer).await?;
+
+    tokio::spawn(async move {
+        let mut last: Option<u32> = None;
+        while let Some(h264) = video_receiver.recv().await {
+            // 编码器输出的 Annex-B 码流中已经带有 SPS/PPS，无需单独发送配置
+            if let H264Data::Data { pts, data, .. } = h264 {
+                // 按帧的时间戳计算时长，不受调度抖动影响
+                let duration = match last {
+                    Some(last) => Duration::from_millis(pts.saturating_sub(last) as u64),
+                    None => Duration::from_millis(40),
+                };
+                last = Some(pts);
+
+                if let Err(e) = video_track
+                    .write_sample(&Sample {
+                        data,
+                        duration,
+                        ..Default::default()
+                    })
+                    .await
+                {
+                    log::error!("[publish] Failed to write video sample: {}", e);
+                }
+            }
+        }
+        log::info!("[publish] video source closed");
+    });
+
+    tokio::spawn(async move {
+        while let Some(packet) = audio_receiver.recv().await {
+            if let Err(e) = audio_track.write_rtp(&packet).await {
+                log::error!("[publish] Failed to write audio packet: {}", e);
+            }
+        }
+        log::info!("[publish] audio source closed");
+    });
+
+    Ok(Publisher {
+        peer_connection,
+        whip,
+    })
+}
+
+/// 读取并丢弃 RTCP 包，拦截器（如 NACK）依赖此读取循环
+fn drain_rtcp(sender: Arc<RTCRtpSender>) {
+    tokio::spawn(async move {
+        let mut buffer = vec![0u8; 1500];
+        while sender.read(&mut buffer).await.is_ok() {}
+    });
+}
+
 const NALU_TTYPE_STAP_A: u32 = 24;
 const NALU_TTYPE_SPS: u32 = 7;
 const NALU_TYPE_BITMASK: u32 = 0x1F;
@@ -374,3 +829,68 @@ fn is_key_frame(data: &[u8]) -> bool {
             || (nalu_type == NALU_TTYPE_SPS)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srs(url: &str) -> (String, u16, String, String) {
+        match url.parse::<PublishTarget>().unwrap() {
+            PublishTarget::Srs {
+                host,
+                port,
+                app,
+                stream,
+            } => (host, port, app, stream),
+            target => panic!("Not an SRS target: {:?}", target),
+        }
+    }
+
+    #[test]
+    fn parse_srs_publish_target() {
+        let target = |host: &str, port, app: &str, stream: &str| {
+            (host.to_string(), port, app.to_string(), stream.to_string())
+        };
+        assert_eq!(
+            srs("webrtc://example.com:1985/live/room"),
+            target("example.com", 1985, "live", "room")
+        );
+        // 未指定端口时为 443，路径中可有多级 app 及末尾的斜杠
+        assert_eq!(
+            srs("webrtc://example.com/live/sub/room/"),
+            target("example.com", 443, "live/sub", "room")
+        );
+        assert_eq!(
+            srs("webrtc://[::1]:1985/live/room"),
+            target("[::1]", 1985, "live", "room")
+        );
+        assert_eq!(
+            srs("webrtc://[2001:db8::1]/live/room"),
+            target("[2001:db8::1]", 443, "live", "room")
+        );
+    }
+
+    #[test]
+    fn parse_whip_publish_target() {
+        let url = "https://example.com/whip/endpoint?token=1";
+        match url.parse::<PublishTarget>().unwrap() {
+            PublishTarget::Whip { endpoint } => assert_eq!(endpoint, url),
+            target => panic!("Not a WHIP target: {:?}", target),
+        }
+    }
+
+    #[test]
+    fn reject_invalid_publish_targets() {
+        for url in [
+            "rtmp://example.com/live/room",
+            "webrtc://example.com",
+            "webrtc://example.com/room",
+            "webrtc://example.com//room",
+            "webrtc://example.com:port/live/room",
+            "webrtc://example.com:70000/live/room",
+            "webrtc://:1985/live/room",
+        ] {
+            assert!(url.parse::<PublishTarget>().is_err(), "{}", url);
+        }
+    }
+}
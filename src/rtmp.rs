@@ -1,5 +1,7 @@
-use crate::h264::H264Data;
+use crate::h264::{annexb_to_avcc, split_annexb, H264Data, NAL_UNIT_TYPE_IDR};
+use crate::h265::{annexb_to_hvcc, is_irap, H265Data};
 use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
 use rml_rtmp::handshake::{HandshakeProcessResult, PeerType};
 use rml_rtmp::sessions::{
     ClientSession, ClientSessionConfig, ClientSessionEvent, ClientSessionResult,
@@ -18,7 +20,7 @@ pub struct RtmpConnection {
 impl RtmpConnection {
     pub async fn connect<A: ToSocketAddrs>(
         addr: A,
-        mut h264_reader: UnboundedReceiver<H264Data>,
+        mut video_reader: UnboundedReceiver<VideoPacket>,
     ) -> anyhow::Result<Self> {
         let mut socket = TcpStream::connect(addr).await?;
 
@@ -52,7 +54,7 @@ impl RtmpConnection {
         };
 
         let mut config = ClientSessionConfig::new();
-        config.tc_url = Some(format!("rtmp://localhost/live"));
+        config.tc_url = Some("rtmp://localhost/live".to_string());
         let (mut session, results) = ClientSession::new(config)?;
 
         for result in results {
@@ -95,30 +97,31 @@ impl RtmpConnection {
                                             RtmpConnection::send_outbound_packet(&mut socket, session.request_publishing("gengteng".to_string(), PublishRequestType::Live)?).await?;
                                         }
                                         ClientSessionEvent::PublishRequestAccepted => {
-                                            let mut metadata = StreamMetadata::new();
-                                            metadata.encoder = Some(format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
-                                            metadata.video_width = Some(320);
-                                            metadata.video_height = Some(240);
-                                            metadata.video_codec = Some("7".to_string());
-                                            RtmpConnection::send_outbound_packet(&mut socket, session.publish_metadata(&metadata)?).await?;
                                             // RtmpConnection::send_outbound_packet(&mut socket, session.publish_video_data(Bytes::new(), RtmpTimestamp::new(0), true)?).await?;
                                             // RtmpConnection::send_outbound_packet(&mut socket, session.publish_audio_data(Bytes::new(), RtmpTimestamp::new(0), true)?).await?;
                                             let mut timestamp = RtmpTimestamp::new(0);
-                                            let mut rtp_ts: Option<u32> = None;
-                                            while let Some(h264) = h264_reader.recv().await {
-                                                if let H264Data::Data { timestamp: h264_ts, .. } = &h264 {
-                                                    match rtp_ts {
-                                                        Some(ts) => {
-                                                            let offset = *h264_ts - ts;
-                                                            timestamp = timestamp + offset;
-                                                            rtp_ts = Some(ts + offset);
-                                                        }
-                                                        None => {
-                                                            rtp_ts = Some(*h264_ts);
-                                                        }
-                                                    }
+                                            let mut first_dts: Option<u32> = None;
+                                            let mut metadata_sent = false;
+                                            let mut muxer = FlvMuxer::default();
+                                            while let Some(packet) = video_reader.recv().await {
+                                                if let Some(dts) = packet.dts() {
+                                                    // RTMP 时间戳为相对首帧的 dts，pts 通过 composition time 表示
+                                                    let first_dts = *first_dts.get_or_insert(dts);
+                                                    timestamp = RtmpTimestamp::new(dts.wrapping_sub(first_dts));
+                                                }
+                                                if !metadata_sent {
+                                                    let metadata = stream_metadata(&packet);
+                                                    RtmpConnection::send_outbound_packet(&mut socket, session.publish_metadata(&metadata)?).await?;
+                                                    metadata_sent = true;
                                                 }
-                                                let data_to_send: bytes::Bytes = h264.into();
+                                                let data_to_send = match muxer.video_tag(packet) {
+                                                    Ok(Some(tag)) => tag,
+                                                    Ok(None) => continue,
+                                                    Err(e) => {
+                                                        log::error!("Failed to mux video packet: {}", e);
+                                                        continue;
+                                                    }
+                                                };
                                                 log::info!("RTMP body: {:02x?}", data_to_send.as_ref());
                                                 // https://blog.csdn.net/jctian000/article/details/93205093
                                                 RtmpConnection::send_outbound_packet(&mut socket, session.publish_video_data(data_to_send, timestamp, true)?).await?;
@@ -197,6 +200,174 @@ impl RtmpConnection {
     }
 }
 
+/// 发送给 RTMP 推流端的视频数据，Configuration 及 Data 中的帧均为 Annex-B 格式
+pub enum VideoPacket {
+    Avc(H264Data),
+    Hevc(H265Data),
+}
+
+impl VideoPacket {
+    fn dts(&self) -> Option<u32> {
+        match self {
+            VideoPacket::Avc(H264Data::Data { dts, .. })
+            | VideoPacket::Hevc(H265Data::Data { dts, .. }) => Some(*dts),
+            _ => None,
+        }
+    }
+}
+
+const CODEC_ID_AVC: u8 = 7;
+const AVC_SEQUENCE_HEADER: u8 = 0;
+const AVC_NALU: u8 = 1;
+
+// Enhanced RTMP: https://github.com/veovera/enhanced-rtmp
+const IS_EX_HEADER: u8 = 0x80;
+const PACKET_TYPE_SEQUENCE_START: u8 = 0;
+const PACKET_TYPE_CODED_FRAMES: u8 = 1;
+const PACKET_TYPE_CODED_FRAMES_X: u8 = 3;
+const FOURCC_HEVC: &[u8; 4] = b"hvc1";
+
+const FRAME_TYPE_KEY: u8 = 1;
+const FRAME_TYPE_INTER: u8 = 2;
+
+/// 将 Annex-B 视频数据封装为 FLV 视频标签体，H264 使用传统格式，H265 使用 Enhanced RTMP
+#[derive(Debug, Default)]
+struct FlvMuxer {
+    /// 最近一次 sequence header 中的 lengthSizeMinusOne，未收到时丢弃帧数据
+    length_size_minus_one: Option<u8>,
+}
+
+impl FlvMuxer {
+    fn video_tag(&mut self, packet: VideoPacket) -> anyhow::Result<Option<Bytes>> {
+        let composition_time = match &packet {
+            VideoPacket::Avc(packet) => packet.composition_time(),
+            VideoPacket::Hevc(packet) => packet.composition_time(),
+        };
+        let mut buffer = BytesMut::new();
+        match packet {
+            VideoPacket::Avc(H264Data::Configuration { record, .. }) => {
+                buffer.put_u8(FRAME_TYPE_KEY << 4 | CODEC_ID_AVC);
+                buffer.put_u8(AVC_SEQUENCE_HEADER);
+                buffer.put_int(0, 3);
+                record.write_to(&mut buffer);
+                self.length_size_minus_one = Some(record.length_size_minus_one);
+            }
+            VideoPacket::Avc(H264Data::Data { data, .. }) => {
+                let length_size_minus_one = match self.length_size_minus_one {
+                    Some(length_size_minus_one) => length_size_minus_one,
+                    None => {
+                        log::warn!("Drop h264 frame before sequence header");
+                        return Ok(None);
+                    }
+                };
+                let key_frame = split_annexb(&data)
+                    .iter()
+                    .any(|nal| crate::h264::nal_unit_type(nal) == NAL_UNIT_TYPE_IDR);
+                let frame_type = if key_frame {
+                    FRAME_TYPE_KEY
+                } else {
+                    FRAME_TYPE_INTER
+                };
+                buffer.put_u8(frame_type << 4 | CODEC_ID_AVC);
+                buffer.put_u8(AVC_NALU);
+                buffer.put_int(composition_time as i64, 3);
+                buffer.put_slice(&annexb_to_avcc(&data, length_size_minus_one)?);
+            }
+            VideoPacket::Hevc(H265Data::Configuration { record, .. }) => {
+                buffer.put_u8(IS_EX_HEADER | FRAME_TYPE_KEY << 4 | PACKET_TYPE_SEQUENCE_START);
+                buffer.put_slice(FOURCC_HEVC);
+                record.write_to(&mut buffer);
+                self.length_size_minus_one = Some(record.length_size_minus_one);
+            }
+            VideoPacket::Hevc(H265Data::Data { data, .. }) => {
+                let length_size_minus_one = match self.length_size_minus_one {
+                    Some(length_size_minus_one) => length_size_minus_one,
+                    None => {
+                        log::warn!("Drop h265 frame before sequence header");
+                        return Ok(None);
+                    }
+                };
+                let frame_type = if split_annexb(&data).iter().any(|nal| is_irap(nal)) {
+                    FRAME_TYPE_KEY
+                } else {
+                    FRAME_TYPE_INTER
+                };
+                // CodedFramesX 省略 composition time，仅在其为 0 时使用
+                if composition_time == 0 {
+                    buffer.put_u8(IS_EX_HEADER | frame_type << 4 | PACKET_TYPE_CODED_FRAMES_X);
+                    buffer.put_slice(FOURCC_HEVC);
+                } else {
+                    buffer.put_u8(IS_EX_HEADER | frame_type << 4 | PACKET_TYPE_CODED_FRAMES);
+                    buffer.put_slice(FOURCC_HEVC);
+                    buffer.put_int(composition_time as i64, 3);
+                }
+                buffer.put_slice(&annexb_to_hvcc(&data, length_size_minus_one)?);
+            }
+        }
+        Ok(Some(buffer.freeze()))
+    }
+}
+
+/// 根据第一个视频包生成 onMetaData，sequence header 中的 SPS 提供分辨率和帧率
+fn stream_metadata(packet: &VideoPacket) -> StreamMetadata {
+    let mut metadata = StreamMetadata::new();
+    metadata.encoder = Some(format!(
+        "{}/{}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    ));
+
+    // rml_rtmp 的 StreamMetadata 没有 profile/level 字段，只能记录日志
+    match packet {
+        VideoPacket::Avc(h264) => {
+            metadata.video_codec = Some(CODEC_ID_AVC.to_string());
+            if let H264Data::Configuration { record, .. } = h264 {
+                match record.sps() {
+                    Ok(sps) => {
+                        log::info!(
+                            "Stream metadata from SPS: {}x{}, {} profile, level {}, frame rate: {:?}",
+                            sps.width(),
+                            sps.height(),
+                            sps.profile_name(),
+                            sps.level(),
+                            sps.frame_rate()
+                        );
+                        metadata.video_width = Some(sps.width());
+                        metadata.video_height = Some(sps.height());
+                        metadata.video_frame_rate = sps.frame_rate().map(|rate| rate as f32);
+                    }
+                    Err(e) => log::warn!("Failed to parse SPS for stream metadata: {}", e),
+                }
+            }
+        }
+        VideoPacket::Hevc(h265) => {
+            metadata.video_codec = Some(String::from_utf8_lossy(FOURCC_HEVC).to_string());
+            if let H265Data::Configuration { record, .. } = h265 {
+                match record.sps() {
+                    Ok(sps) => {
+                        log::info!(
+                            "Stream metadata from SPS: {}x{}, {} profile, level {}",
+                            sps.width(),
+                            sps.height(),
+                            sps.profile_name(),
+                            sps.level()
+                        );
+                        metadata.video_width = Some(sps.width());
+                        metadata.video_height = Some(sps.height());
+                    }
+                    Err(e) => log::warn!("Failed to parse SPS for stream metadata: {}", e),
+                }
+            }
+        }
+    }
+
+    if metadata.video_width.is_none() {
+        log::warn!("First video packet is not a sequence header, no video size in metadata");
+    }
+
+    metadata
+}
+
 #[derive(Debug, Clone)]
 pub enum Command {
     Publish { app: String },
@@ -0,0 +1,214 @@
+use crate::control::JobControl;
+use crate::param::{
+    ColorAdjustment, EncoderSettings, ImageOverlay, Layout, TextOverlay, VideoPosition,
+};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde::de::DeserializeOwned;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// 启动 HTTP 控制接口
+///
+/// - `GET /api/v1/encoder`：查询编码参数
+/// - `PUT /api/v1/encoder`：修改编码参数，请求体为完整的 JSON 参数
+/// - `GET /api/v1/layout`：查询布局
+/// - `PUT /api/v1/layout`：替换整个布局
+/// - `PUT /api/v1/layout/videos/{id}`：替换某个 tile 的位置、取景区域等
+/// - `PUT /api/v1/layout/videos/{id}/color`：调整某个 tile 的亮度、对比度、饱和度及 gamma
+/// - `GET /api/v1/speaker`：查询当前发言人、各路源的音量及最近的切换事件
+/// - `PUT /api/v1/layout/images/{id}`：添加或替换图片叠加
+/// - `DELETE /api/v1/layout/images/{id}`：删除图片叠加
+/// - `PUT /api/v1/layout/texts/{id}`：添加或替换文字叠加
+/// - `DELETE /api/v1/layout/texts/{id}`：删除文字叠加
+pub async fn serve(addr: SocketAddr, control: JobControl) -> anyhow::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let control = control.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let control = control.clone();
+                async move { Ok::<_, Infallible>(handle(request, control).await) }
+            }))
+        }
+    });
+
+    log::info!("API server listening on {}", addr);
+    Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle(request: Request<Body>, control: JobControl) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    match (&method, path.as_str()) {
+        (&Method::GET, "/api/v1/encoder") => json_response(&*control.encoder.borrow()),
+        (&Method::PUT, "/api/v1/encoder") => {
+            let settings: EncoderSettings = match read_json(request).await {
+                Ok(settings) => settings,
+                Err(response) => return response,
+            };
+            if let Err(e) = settings.validate() {
+                return error_response(StatusCode::BAD_REQUEST, e);
+            }
+            log::info!("Update encoder settings: {:?}", settings);
+            control.encoder.send_replace(settings.clone());
+            json_response(&settings)
+        }
+        (&Method::GET, "/api/v1/speaker") => json_response(&control.speaker.status(Instant::now())),
+        (&Method::GET, "/api/v1/layout") => json_response(&*control.layout.borrow()),
+        (&Method::PUT, "/api/v1/layout") => {
+            let layout: Layout = match read_json(request).await {
+                Ok(layout) => layout,
+                Err(response) => return response,
+            };
+            if let Err(e) = layout.validate(&control.canvas, &control.filters) {
+                return error_response(StatusCode::BAD_REQUEST, e);
+            }
+            log::info!("Update layout: {:?}", layout);
+            control.layout.send_replace(layout.clone());
+            json_response(&layout)
+        }
+        (&Method::PUT, _)
+            if path.starts_with("/api/v1/layout/videos/") && path.ends_with("/color") =>
+        {
+            let id = path
+                .strip_prefix("/api/v1/layout/videos/")
+                .and_then(|rest| rest.strip_suffix("/color"))
+                .unwrap_or_default();
+            if id.is_empty() {
+                return error_response(StatusCode::NOT_FOUND, "Not found");
+            }
+            let adjustment: ColorAdjustment = match read_json(request).await {
+                Ok(adjustment) => adjustment,
+                Err(response) => return response,
+            };
+            if let Err(e) = adjustment.validate() {
+                return error_response(StatusCode::BAD_REQUEST, e);
+            }
+            let mut layout = control.layout.borrow().clone();
+            match layout.videos.iter_mut().find(|video| video.id == id) {
+                Some(video) => video.color_adjustment = Some(adjustment),
+                None => return error_response(StatusCode::NOT_FOUND, format!("No video {}", id)),
+            }
+            control.layout.send_replace(layout);
+            log::info!("Update color adjustment of {}: {:?}", id, adjustment);
+            json_response(&adjustment)
+        }
+        (&Method::PUT, _) if path.starts_with("/api/v1/layout/videos/") => {
+            let id = &path["/api/v1/layout/videos/".len()..];
+            let mut position: VideoPosition = match read_json(request).await {
+                Ok(position) => position,
+                Err(response) => return response,
+            };
+            position.id = id.to_string();
+            if let Err(e) = position.validate(&control.canvas, &control.filters) {
+                return error_response(StatusCode::BAD_REQUEST, e);
+            }
+            let mut layout = control.layout.borrow().clone();
+            match layout.videos.iter_mut().find(|video| video.id == id) {
+                Some(video) => *video = position.clone(),
+                None => return error_response(StatusCode::NOT_FOUND, format!("No video {}", id)),
+            }
+            control.layout.send_replace(layout);
+            log::info!("Update video position: {:?}", position);
+            json_response(&position)
+        }
+        (&Method::PUT, _) if path.starts_with("/api/v1/layout/images/") => {
+            let id = &path["/api/v1/layout/images/".len()..];
+            let mut image: ImageOverlay = match read_json(request).await {
+                Ok(image) => image,
+                Err(response) => return response,
+            };
+            image.id = id.to_string();
+            if let Err(e) = image.validate(&control.canvas) {
+                return error_response(StatusCode::BAD_REQUEST, e);
+            }
+            let mut layout = control.layout.borrow().clone();
+            match layout.images.iter_mut().find(|i| i.id == id) {
+                Some(i) => *i = image.clone(),
+                None => layout.images.push(image.clone()),
+            }
+            control.layout.send_replace(layout);
+            log::info!("Update image overlay: {:?}", image);
+            json_response(&image)
+        }
+        (&Method::DELETE, _) if path.starts_with("/api/v1/layout/images/") => {
+            let id = &path["/api/v1/layout/images/".len()..];
+            let mut layout = control.layout.borrow().clone();
+            let count = layout.images.len();
+            layout.images.retain(|i| i.id != id);
+            if layout.images.len() == count {
+                return error_response(StatusCode::NOT_FOUND, format!("No image {}", id));
+            }
+            control.layout.send_replace(layout);
+            log::info!("Remove image overlay: {}", id);
+            no_content()
+        }
+        (&Method::PUT, _) if path.starts_with("/api/v1/layout/texts/") => {
+            let id = &path["/api/v1/layout/texts/".len()..];
+            let mut text: TextOverlay = match read_json(request).await {
+                Ok(text) => text,
+                Err(response) => return response,
+            };
+            text.id = id.to_string();
+            if let Err(e) = text.validate() {
+                return error_response(StatusCode::BAD_REQUEST, e);
+            }
+            let mut layout = control.layout.borrow().clone();
+            match layout.texts.iter_mut().find(|t| t.id == id) {
+                Some(t) => *t = text.clone(),
+                None => layout.texts.push(text.clone()),
+            }
+            control.layout.send_replace(layout);
+            log::info!("Update text overlay: {:?}", text);
+            json_response(&text)
+        }
+        (&Method::DELETE, _) if path.starts_with("/api/v1/layout/texts/") => {
+            let id = &path["/api/v1/layout/texts/".len()..];
+            let mut layout = control.layout.borrow().clone();
+            let count = layout.texts.len();
+            layout.texts.retain(|t| t.id != id);
+            if layout.texts.len() == count {
+                return error_response(StatusCode::NOT_FOUND, format!("No text {}", id));
+            }
+            control.layout.send_replace(layout);
+            log::info!("Remove text overlay: {}", id);
+            no_content()
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+async fn read_json<T: DeserializeOwned>(request: Request<Body>) -> Result<T, Response<Body>> {
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, e))?;
+    serde_json::from_slice(&body).map_err(|e| error_response(StatusCode::BAD_REQUEST, e))
+}
+
+fn json_response<T: serde::Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+fn no_content() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, error: impl std::fmt::Display) -> Response<Body> {
+    let body = serde_json::json!({ "error": error.to_string() });
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
@@ -0,0 +1,233 @@
+use crate::param::SpeakerDetection;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// 保留的发言人切换事件数
+const MAX_EVENTS: usize = 32;
+
+/// 电平平滑系数，每个音频包占新电平的权重
+const SMOOTHING: f32 = 0.2;
+
+/// 某一路源的音量及语音状态
+#[derive(Debug)]
+struct SourceLevel {
+    /// 平滑后的电平（dBov，0 为最大）
+    level: f32,
+    voice: bool,
+    /// 电平持续高于阈值的起始时刻
+    above_since: Option<Instant>,
+    /// 电平持续低于阈值的起始时刻
+    below_since: Option<Instant>,
+    updated: Instant,
+}
+
+/// 发言人切换事件
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerEvent {
+    /// Unix 时间戳（毫秒）
+    pub timestamp: u64,
+    pub previous: Option<String>,
+    pub speaker: String,
+}
+
+/// 某一路源的状态，用于控制接口查询
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceStatus {
+    pub id: String,
+    pub level: f32,
+    pub voice: bool,
+}
+
+/// 当前发言人、各路源的音量及最近的切换事件
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeakerStatus {
+    pub speaker: Option<String>,
+    pub sources: Vec<SourceStatus>,
+    pub events: Vec<SpeakerEvent>,
+}
+
+#[derive(Debug)]
+struct DetectorState {
+    settings: SpeakerDetection,
+    sources: HashMap<String, SourceLevel>,
+    speaker: Option<String>,
+    /// 比当前发言人声音更大的源及其开始的时刻
+    challenger: Option<(String, Instant)>,
+    events: VecDeque<SpeakerEvent>,
+}
+
+/// 根据各路源的音量检测当前发言人，带迟滞避免频繁切换
+///
+/// 音量来自 RTP 的 ssrc-audio-level 头扩展（RFC 6464），由拉流线程写入，输出时钟读取。
+/// 不解码 Opus 计算电平，未协商该扩展的源始终不会被检测为发言人
+#[derive(Debug, Clone)]
+pub struct SpeakerDetector(Arc<Mutex<DetectorState>>);
+
+impl SpeakerDetector {
+    pub fn new(settings: SpeakerDetection) -> Self {
+        Self(Arc::new(Mutex::new(DetectorState {
+            settings,
+            sources: HashMap::new(),
+            speaker: None,
+            challenger: None,
+            events: VecDeque::new(),
+        })))
+    }
+
+    /// 记录一个音频包的电平，level 为 RFC 6464 中的 -dBov（0 ~ 127）
+    pub fn update(&self, id: &str, level: u8, now: Instant) {
+        let mut state = self.0.lock().unwrap();
+        let threshold = state.settings.threshold;
+        let attack = Duration::from_secs_f64(state.settings.attack_secs.max(0.0));
+        let release = Duration::from_secs_f64(state.settings.release_secs.max(0.0));
+        let level = -(level.min(127) as f32);
+        let source = state
+            .sources
+            .entry(id.to_string())
+            .or_insert_with(|| SourceLevel {
+                level,
+                voice: false,
+                above_since: None,
+                below_since: None,
+                updated: now,
+            });
+        source.level += (level - source.level) * SMOOTHING;
+        source.updated = now;
+        if source.level > threshold {
+            source.below_since = None;
+            let since = *source.above_since.get_or_insert(now);
+            if !source.voice && now.saturating_duration_since(since) >= attack {
+                source.voice = true;
+            }
+        } else {
+            source.above_since = None;
+            let since = *source.below_since.get_or_insert(now);
+            if source.voice && now.saturating_duration_since(since) >= release {
+                source.voice = false;
+            }
+        }
+    }
+
+    /// 当前发言人，没有人说话时保持上一个发言人
+    pub fn speaker(&self, now: Instant) -> Option<String> {
+        let mut state = self.0.lock().unwrap();
+        state.evaluate(now);
+        state.speaker.clone()
+    }
+
+    pub fn status(&self, now: Instant) -> SpeakerStatus {
+        let mut state = self.0.lock().unwrap();
+        state.evaluate(now);
+        let mut sources: Vec<SourceStatus> = state
+            .sources
+            .iter()
+            .map(|(id, source)| SourceStatus {
+                id: id.clone(),
+                level: source.level,
+                voice: state.is_voice(source, now),
+            })
+            .collect();
+        sources.sort_by(|a, b| a.id.cmp(&b.id));
+        SpeakerStatus {
+            speaker: state.speaker.clone(),
+            sources,
+            events: state.events.iter().cloned().collect(),
+        }
+    }
+}
+
+impl DetectorState {
+    /// 超过 release 时长没有音频包的源视为静音
+    fn is_voice(&self, source: &SourceLevel, now: Instant) -> bool {
+        let release = Duration::from_secs_f64(self.settings.release_secs.max(0.0));
+        source.voice && now.saturating_duration_since(source.updated) <= release
+    }
+
+    fn evaluate(&mut self, now: Instant) {
+        let loudest = self
+            .sources
+            .iter()
+            .filter(|(_, source)| self.is_voice(source, now))
+            .max_by(|(_, a), (_, b)| a.level.total_cmp(&b.level))
+            .map(|(id, _)| id.clone());
+        let loudest = match loudest {
+            Some(loudest) if Some(&loudest) != self.speaker.as_ref() => loudest,
+            _ => {
+                self.challenger = None;
+                return;
+            }
+        };
+
+        // 没有发言人或发言人已静音时立即切换，否则需持续更响一段时间
+        let speaking = self
+            .speaker
+            .as_ref()
+            .and_then(|id| self.sources.get(id))
+            .is_some_and(|source| self.is_voice(source, now));
+        let switch = Duration::from_secs_f64(self.settings.switch_secs.max(0.0));
+        let since = match &self.challenger {
+            Some((id, since)) if *id == loudest => *since,
+            _ => {
+                self.challenger = Some((loudest.clone(), now));
+                now
+            }
+        };
+        if speaking && now.saturating_duration_since(since) < switch {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let previous = self.speaker.replace(loudest.clone());
+        log::info!("Active speaker changed: {:?} -> {}", previous, loudest);
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(SpeakerEvent {
+            timestamp,
+            previous,
+            speaker: loudest,
+        });
+        self.challenger = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(detector: &SpeakerDetector, id: &str, level: u8, start: Instant, millis: u64) {
+        for t in (0..millis).step_by(20) {
+            detector.update(id, level, start + Duration::from_millis(t));
+        }
+    }
+
+    #[test]
+    fn switch_to_louder_speaker_with_hysteresis() {
+        let detector = SpeakerDetector::new(SpeakerDetection::default());
+        let start = Instant::now();
+        feed(&detector, "a", 20, start, 1000);
+        feed(&detector, "b", 90, start, 1000);
+        let t = start + Duration::from_millis(1000);
+        assert_eq!(detector.speaker(t).as_deref(), Some("a"));
+
+        // b 更响但持续时间不足，不切换
+        feed(&detector, "b", 10, t, 500);
+        feed(&detector, "a", 20, t, 500);
+        let t = t + Duration::from_millis(500);
+        assert_eq!(detector.speaker(t).as_deref(), Some("a"));
+
+        feed(&detector, "b", 10, t, 2000);
+        feed(&detector, "a", 20, t, 2000);
+        let t = t + Duration::from_millis(2000);
+        assert_eq!(detector.speaker(t).as_deref(), Some("b"));
+
+        let status = detector.status(t);
+        assert_eq!(status.events.len(), 2);
+        assert_eq!(status.events[1].previous.as_deref(), Some("a"));
+    }
+}
@@ -0,0 +1,308 @@
+use crate::param::{Canvas, Template, TemplateKind, VideoPosition, MAX_ASPECT, MIN_ASPECT};
+use crate::yuv::Rect;
+
+/// 画中画小窗宽度占画布的比例
+const PIP_SCALE: usize = 4;
+
+/// 主画面下方条带高度占画布的比例
+const STRIP_SCALE: usize = 5;
+
+/// 按模板为各路源生成 tile，主画面排在第一个
+pub fn generate(template: &Template, canvas: &Canvas, sources: &[String]) -> Vec<VideoPosition> {
+    if sources.is_empty() {
+        return Vec::new();
+    }
+    let mut ordered: Vec<&String> = sources.iter().collect();
+    if let Some(index) = template
+        .main
+        .as_ref()
+        .and_then(|main| ordered.iter().position(|id| *id == main))
+    {
+        let main = ordered.remove(index);
+        ordered.insert(0, main);
+    }
+
+    let padding = template.padding as usize;
+    let area = Rect::new(
+        padding as isize,
+        padding as isize,
+        (canvas.width as usize).saturating_sub(padding * 2),
+        (canvas.height as usize).saturating_sub(padding * 2),
+    );
+    let gap = template.gap as usize;
+    let aspect = template
+        .aspect
+        .filter(|aspect| (MIN_ASPECT..=MAX_ASPECT).contains(aspect));
+    // 小窗及条带中的 tile 必须有宽高比，默认与画布一致
+    let small_aspect = aspect
+        .unwrap_or(canvas.width as f32 / canvas.height.max(1) as f32)
+        .clamp(MIN_ASPECT, MAX_ASPECT);
+    let n = ordered.len();
+    let tiles = match template.kind {
+        TemplateKind::Grid => grid(area, n, gap, aspect),
+        TemplateKind::SideBySide => split(area, n, gap, true)
+            .into_iter()
+            .map(|cell| fit(cell, aspect))
+            .collect(),
+        TemplateKind::VerticalStack => split(area, n, gap, false)
+            .into_iter()
+            .map(|cell| fit(cell, aspect))
+            .collect(),
+        TemplateKind::SpeakerStrip => speaker_strip(area, n, gap, aspect, small_aspect),
+        TemplateKind::PictureInPicture => picture_in_picture(area, n, gap, aspect, small_aspect),
+    };
+
+    ordered
+        .into_iter()
+        .zip(tiles)
+        .map(|(id, (rect, layer))| {
+            let mut position = VideoPosition::new(
+                id.clone(),
+                even(rect.x.max(0) as usize),
+                even(rect.y.max(0) as usize),
+                even(rect.width),
+                even(rect.height),
+            );
+            position.layer = layer;
+            position.mode = template.mode;
+            position
+        })
+        .collect()
+}
+
+/// 坐标及尺寸向下取偶数，与 4:2:0 色度采样对齐
+fn even(value: usize) -> u32 {
+    (value & !1) as u32
+}
+
+/// 将区域等分为 n 份，horizontal 为 true 时水平排列，余数分给前面的格子
+fn split(area: Rect, n: usize, gap: usize, horizontal: bool) -> Vec<Rect> {
+    let total = if horizontal { area.width } else { area.height };
+    let available = total.saturating_sub(gap * (n - 1));
+    let mut offset = 0;
+    (0..n)
+        .map(|i| {
+            let size = available / n + usize::from(i < available % n);
+            let cell = if horizontal {
+                Rect::new(area.x + offset as isize, area.y, size, area.height)
+            } else {
+                Rect::new(area.x, area.y + offset as isize, area.width, size)
+            };
+            offset += size + gap;
+            cell
+        })
+        .collect()
+}
+
+/// 在格子内居中放置指定宽高比的最大矩形，不指定宽高比则铺满
+fn fit(cell: Rect, aspect: Option<f32>) -> (Rect, u32) {
+    let aspect = match aspect {
+        Some(aspect) => aspect,
+        None => return (cell, 0),
+    };
+    let (mut width, mut height) = (cell.width, (cell.width as f32 / aspect) as usize);
+    if height > cell.height {
+        height = cell.height;
+        width = (cell.height as f32 * aspect) as usize;
+    }
+    let x = cell.x + ((cell.width - width) / 2) as isize;
+    let y = cell.y + ((cell.height - height) / 2) as isize;
+    (Rect::new(x, y, width, height), 0)
+}
+
+/// 宫格，选择使 tile 面积最大的列数，最后一行不满时居中
+fn grid(area: Rect, n: usize, gap: usize, aspect: Option<f32>) -> Vec<(Rect, u32)> {
+    let columns = match aspect {
+        Some(_) => (1..=n)
+            .max_by_key(|&columns| {
+                let rows = n.div_ceil(columns);
+                let cell = grid_cell(area, columns, rows, gap);
+                let (tile, _) = fit(cell, aspect);
+                tile.width * tile.height
+            })
+            .unwrap_or(1),
+        None => (1..=n).find(|c| c * c >= n).unwrap_or(n),
+    };
+    let rows = n.div_ceil(columns);
+    let cell = grid_cell(area, columns, rows, gap);
+    (0..n)
+        .map(|i| {
+            let (row, column) = (i / columns, i % columns);
+            let in_row = (n - row * columns).min(columns);
+            let indent = (columns - in_row) * (cell.width + gap) / 2;
+            let x = area.x + (indent + column * (cell.width + gap)) as isize;
+            let y = area.y + (row * (cell.height + gap)) as isize;
+            fit(Rect::new(x, y, cell.width, cell.height), aspect)
+        })
+        .collect()
+}
+
+fn grid_cell(area: Rect, columns: usize, rows: usize, gap: usize) -> Rect {
+    Rect::new(
+        0,
+        0,
+        area.width.saturating_sub(gap * (columns - 1)) / columns,
+        area.height.saturating_sub(gap * (rows - 1)) / rows,
+    )
+}
+
+/// 主画面在上，其余源在下方条带中居中排成一行
+fn speaker_strip(
+    area: Rect,
+    n: usize,
+    gap: usize,
+    aspect: Option<f32>,
+    small_aspect: f32,
+) -> Vec<(Rect, u32)> {
+    if n == 1 {
+        return vec![fit(area, aspect)];
+    }
+    let strip_height = area.height / STRIP_SCALE;
+    let main = Rect::new(
+        area.x,
+        area.y,
+        area.width,
+        area.height.saturating_sub(strip_height + gap),
+    );
+    let count = n - 1;
+    let mut height = strip_height;
+    let mut width = (height as f32 * small_aspect) as usize;
+    if width * count + gap * (count - 1) > area.width {
+        width = area.width.saturating_sub(gap * (count - 1)) / count;
+        height = (width as f32 / small_aspect) as usize;
+    }
+    let row_width = width * count + gap * (count - 1);
+    let x = area.x + ((area.width - row_width.min(area.width)) / 2) as isize;
+    let y = area.y + (area.height - height) as isize;
+
+    let mut tiles = vec![fit(main, aspect)];
+    tiles.extend((0..count).map(|i| {
+        let rect = Rect::new(x + (i * (width + gap)) as isize, y, width, height);
+        (rect, 0)
+    }));
+    tiles
+}
+
+/// 主画面铺满，其余源为右下角从右向左排列的小窗
+fn picture_in_picture(
+    area: Rect,
+    n: usize,
+    gap: usize,
+    aspect: Option<f32>,
+    small_aspect: f32,
+) -> Vec<(Rect, u32)> {
+    let width = area.width / PIP_SCALE;
+    let height = (width as f32 / small_aspect) as usize;
+    let right = area.x + area.width as isize - gap as isize;
+    let y = area.y + area.height as isize - (gap + height) as isize;
+
+    let mut tiles = vec![fit(area, aspect)];
+    tiles.extend((1..n).map(|i| {
+        let x = right - (i * width + (i - 1) * gap) as isize;
+        (Rect::new(x, y, width, height), 1)
+    }));
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canvas() -> Canvas {
+        Canvas {
+            width: 1280,
+            height: 720,
+            fps: 25,
+            background: Default::default(),
+        }
+    }
+
+    fn sources(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("s{}", i)).collect()
+    }
+
+    fn rects(positions: &[VideoPosition]) -> Vec<(u32, u32, u32, u32)> {
+        positions
+            .iter()
+            .map(|p| (p.x, p.y, p.width, p.height))
+            .collect()
+    }
+
+    #[test]
+    fn grid_centers_last_row() {
+        let template = Template::new(TemplateKind::Grid);
+        let positions = generate(&template, &canvas(), &sources(3));
+        assert_eq!(
+            rects(&positions),
+            vec![(0, 0, 640, 360), (640, 0, 640, 360), (320, 360, 640, 360)]
+        );
+    }
+
+    #[test]
+    fn grid_with_gap_and_aspect() {
+        let mut template = Template::new(TemplateKind::Grid);
+        template.padding = 10;
+        template.gap = 20;
+        template.aspect = Some(16.0 / 9.0);
+        let positions = generate(&template, &canvas(), &sources(2));
+        // 两列时 tile 最大，垂直居中
+        assert_eq!(
+            rects(&positions),
+            vec![(10, 186, 620, 348), (650, 186, 620, 348)]
+        );
+    }
+
+    #[test]
+    fn main_source_comes_first() {
+        let mut template = Template::new(TemplateKind::PictureInPicture);
+        template.main = Some("s1".to_string());
+        let positions = generate(&template, &canvas(), &sources(2));
+        assert_eq!(positions[0].id, "s1");
+        assert_eq!(rects(&positions)[0], (0, 0, 1280, 720));
+        assert_eq!(positions[1].id, "s0");
+        assert_eq!(positions[1].layer, 1);
+        assert_eq!(rects(&positions)[1], (960, 540, 320, 180));
+    }
+
+    #[test]
+    fn speaker_strip_below_main() {
+        let mut template = Template::new(TemplateKind::SpeakerStrip);
+        template.gap = 8;
+        let positions = generate(&template, &canvas(), &sources(3));
+        assert_eq!(
+            rects(&positions),
+            vec![
+                (0, 0, 1280, 568),
+                (380, 576, 256, 144),
+                (644, 576, 256, 144)
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_out_of_range_aspect() {
+        let mut template = Template::new(TemplateKind::SpeakerStrip);
+        template.aspect = Some(1e30);
+        assert!(template.validate(&canvas()).is_err());
+        // 未经检查的宽高比按未设置处理
+        let positions = generate(&template, &canvas(), &sources(3));
+        assert_eq!(rects(&positions)[1], (384, 576, 256, 144));
+        template.aspect = Some(4.0 / 3.0);
+        assert!(template.validate(&canvas()).is_ok());
+    }
+
+    #[test]
+    fn reject_out_of_range_spacing() {
+        let mut template = Template::new(TemplateKind::Grid);
+        template.leave_secs = 1e300;
+        assert!(template.validate(&canvas()).is_err());
+        template.leave_secs = 5.0;
+        template.padding = 360;
+        assert!(template.validate(&canvas()).is_err());
+        template.padding = 10;
+        template.gap = 720;
+        assert!(template.validate(&canvas()).is_err());
+        template.gap = 20;
+        assert!(template.validate(&canvas()).is_ok());
+    }
+}
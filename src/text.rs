@@ -0,0 +1,62 @@
+use crate::yuv::AlphaMask;
+use ab_glyph::{point, Font, FontArc, Glyph, PxScale, ScaleFont};
+use std::path::Path;
+
+/// TrueType/OpenType 字体，用于将文字栅格化为透明度遮罩
+#[derive(Clone)]
+pub struct TextRenderer {
+    font: FontArc,
+}
+
+impl TextRenderer {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read font {}: {}", path.display(), e))?;
+        let font = FontArc::try_from_vec(data)
+            .map_err(|e| anyhow::anyhow!("Invalid font {}: {}", path.display(), e))?;
+        Ok(Self { font })
+    }
+
+    /// 将单行文字栅格化，遮罩高度为字体行高，宽度为文字排版宽度
+    pub fn rasterize(&self, text: &str, size: f32) -> AlphaMask {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let glyphs = layout(&font, text);
+        let width = glyphs
+            .last()
+            .map(|glyph| glyph.position.x + font.h_advance(glyph.id))
+            .unwrap_or(0.0)
+            .ceil() as usize;
+        let height = font.height().ceil() as usize;
+
+        let mut mask = AlphaMask::new(width, height);
+        for glyph in glyphs {
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, coverage| {
+                    let x = bounds.min.x as i32 + x as i32;
+                    let y = bounds.min.y as i32 + y as i32;
+                    if x >= 0 && y >= 0 {
+                        let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+                        mask.accumulate(x as usize, y as usize, alpha);
+                    }
+                });
+            }
+        }
+        mask
+    }
+}
+
+/// 按字距排列字形，基线位于 ascent 处
+fn layout<F: Font, SF: ScaleFont<F>>(font: &SF, text: &str) -> Vec<Glyph> {
+    let mut glyphs: Vec<Glyph> = Vec::new();
+    let mut x = 0.0;
+    for c in text.chars().filter(|c| !c.is_control()) {
+        let id = font.glyph_id(c);
+        if let Some(previous) = glyphs.last() {
+            x += font.kern(previous.id, id);
+        }
+        glyphs.push(id.with_scale_and_position(font.scale(), point(x, font.ascent())));
+        x += font.h_advance(id);
+    }
+    glyphs
+}
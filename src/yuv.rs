@@ -0,0 +1,818 @@
+use crate::param::{ColorAdjustment, Rotation};
+use openh264::formats::YUVSource;
+use std::path::Path;
+
+/// 连续存储的 YUV420P 帧，各平面无填充
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YuvFrame {
+    width: usize,
+    height: usize,
+    y: Vec<u8>,
+    u: Vec<u8>,
+    v: Vec<u8>,
+}
+
+impl YuvFrame {
+    /// 创建纯色帧
+    pub fn new(width: usize, height: usize, (y, u, v): (u8, u8, u8)) -> Self {
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        Self {
+            width,
+            height,
+            y: vec![y; width * height],
+            u: vec![u; chroma_width * chroma_height],
+            v: vec![v; chroma_width * chroma_height],
+        }
+    }
+
+    /// 复制解码器输出的帧，去掉各平面的填充
+    pub fn copy_from<YUV: YUVSource>(yuv: &YUV) -> Self {
+        let width = yuv.width() as usize;
+        let height = yuv.height() as usize;
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        Self {
+            width,
+            height,
+            y: copy_plane(yuv.y(), yuv.y_stride() as usize, width, height),
+            u: copy_plane(
+                yuv.u(),
+                yuv.u_stride() as usize,
+                chroma_width,
+                chroma_height,
+            ),
+            v: copy_plane(
+                yuv.v(),
+                yuv.v_stride() as usize,
+                chroma_width,
+                chroma_height,
+            ),
+        }
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// 由 RGB24 数据转换，色度取 2x2 像素的平均值
+    pub fn from_rgb(width: usize, height: usize, rgb: &[u8]) -> Self {
+        assert_eq!(rgb.len(), width * height * 3);
+        let mut frame = Self::new(width, height, (0, 0, 0));
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        for (i, pixel) in rgb.chunks_exact(3).enumerate() {
+            frame.y[i] = rgb_to_yuv(pixel[0], pixel[1], pixel[2]).0;
+        }
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                let (mut u, mut v, mut count) = (0u32, 0u32, 0u32);
+                for y in (cy * 2)..(cy * 2 + 2).min(height) {
+                    for x in (cx * 2)..(cx * 2 + 2).min(width) {
+                        let p = &rgb[(y * width + x) * 3..];
+                        let (_, pu, pv) = rgb_to_yuv(p[0], p[1], p[2]);
+                        u += pu as u32;
+                        v += pv as u32;
+                        count += 1;
+                    }
+                }
+                frame.u[cy * chroma_width + cx] = ((u + count / 2) / count) as u8;
+                frame.v[cy * chroma_width + cx] = ((v + count / 2) / count) as u8;
+            }
+        }
+        frame
+    }
+
+    /// 读取 PNG/JPEG 图片
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let image = image::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open image {}: {}", path.display(), e))?
+            .to_rgb8();
+        let (width, height) = image.dimensions();
+        Ok(Self::from_rgb(
+            width as usize,
+            height as usize,
+            image.as_raw(),
+        ))
+    }
+
+    /// 由 RGBA 数据转换为画面及透明度遮罩，色度按透明度加权平均，避免透明像素的颜色渗入边缘
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> (Self, AlphaMask) {
+        assert_eq!(rgba.len(), width * height * 4);
+        let mut frame = Self::new(width, height, (0, 128, 128));
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        let mut alpha = AlphaMask::new(width, height);
+        for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+            frame.y[i] = rgb_to_yuv(pixel[0], pixel[1], pixel[2]).0;
+            alpha.data[i] = pixel[3];
+        }
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                let (mut u, mut v, mut weight) = (0u32, 0u32, 0u32);
+                for y in (cy * 2)..(cy * 2 + 2).min(height) {
+                    for x in (cx * 2)..(cx * 2 + 2).min(width) {
+                        let p = &rgba[(y * width + x) * 4..];
+                        let (_, pu, pv) = rgb_to_yuv(p[0], p[1], p[2]);
+                        let a = p[3] as u32;
+                        u += pu as u32 * a;
+                        v += pv as u32 * a;
+                        weight += a;
+                    }
+                }
+                // 全透明时保持中性色度
+                if weight == 0 {
+                    continue;
+                }
+                frame.u[cy * chroma_width + cx] = ((u + weight / 2) / weight) as u8;
+                frame.v[cy * chroma_width + cx] = ((v + weight / 2) / weight) as u8;
+            }
+        }
+        (frame, alpha)
+    }
+
+    /// 读取 PNG/JPEG 图片及其透明通道，无透明通道的图片完全不透明
+    pub fn open_rgba(path: &Path) -> anyhow::Result<(Self, AlphaMask)> {
+        let image = image::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open image {}: {}", path.display(), e))?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+        Ok(Self::from_rgba(
+            width as usize,
+            height as usize,
+            image.as_raw(),
+        ))
+    }
+
+    /// 整帧区域
+    pub fn rect(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// 以纯色填充整帧
+    pub fn fill(&mut self, (y, u, v): (u8, u8, u8)) {
+        self.y.fill(y);
+        self.u.fill(u);
+        self.v.fill(v);
+    }
+
+    /// 将 src 的 src_rect 区域双线性缩放到本帧的 dst_rect 区域，超出本帧的部分被裁掉
+    pub fn draw_scaled(&mut self, src: &YuvFrame, src_rect: Rect, dst_rect: Rect) {
+        self.draw(src, src_rect, dst_rect, None);
+    }
+
+    /// 可分离的盒式模糊，重复三次近似高斯模糊
+    pub fn blur(&mut self, radius: usize) {
+        if radius == 0 {
+            return;
+        }
+        let (chroma_width, chroma_height) = chroma_size(self.width, self.height);
+        let chroma_radius = (radius / 2).max(1);
+        let mut scratch = Vec::new();
+        for _ in 0..3 {
+            box_blur_plane(&mut self.y, self.width, self.height, radius, &mut scratch);
+            box_blur_plane(
+                &mut self.u,
+                chroma_width,
+                chroma_height,
+                chroma_radius,
+                &mut scratch,
+            );
+            box_blur_plane(
+                &mut self.v,
+                chroma_width,
+                chroma_height,
+                chroma_radius,
+                &mut scratch,
+            );
+        }
+    }
+
+    /// 与 [`draw_scaled`](Self::draw_scaled) 相同，但按位于 (mask_x, mask_y) 的遮罩与本帧混合
+    pub fn draw_blended(
+        &mut self,
+        src: &YuvFrame,
+        src_rect: Rect,
+        dst_rect: Rect,
+        mask: &AlphaMask,
+        (mask_x, mask_y): (isize, isize),
+    ) {
+        self.draw(src, src_rect, dst_rect, Some((mask, mask_x, mask_y)));
+    }
+
+    fn draw(
+        &mut self,
+        src: &YuvFrame,
+        src_rect: Rect,
+        dst_rect: Rect,
+        mask: Option<(&AlphaMask, isize, isize)>,
+    ) {
+        if src_rect.is_empty() || dst_rect.is_empty() {
+            return;
+        }
+        let (chroma_width, chroma_height) = chroma_size(self.width, self.height);
+        let (src_chroma_width, _) = chroma_size(src.width, src.height);
+        let luma_alpha = mask.map(|(mask, x, y)| move |px, py| mask.alpha_at(x, y, px, py));
+        scale_plane(
+            Plane::new(&src.y, src.width),
+            src_rect,
+            PlaneMut::new(&mut self.y, self.width, self.height),
+            dst_rect,
+            luma_alpha
+                .as_ref()
+                .map(|f| f as &dyn Fn(isize, isize) -> u8),
+        );
+        let chroma_alpha = mask.map(|(mask, x, y)| move |cx, cy| mask.chroma_alpha(x, y, cx, cy));
+        for (src_plane, dst_plane) in [(&src.u, &mut self.u), (&src.v, &mut self.v)] {
+            scale_plane(
+                Plane::new(src_plane, src_chroma_width),
+                src_rect.chroma(),
+                PlaneMut::new(dst_plane, chroma_width, chroma_height),
+                dst_rect.chroma(),
+                chroma_alpha
+                    .as_ref()
+                    .map(|f| f as &dyn Fn(isize, isize) -> u8),
+            );
+        }
+    }
+}
+
+impl YuvFrame {
+    /// 抠像：按色度与关键色 (u, v) 的距离生成透明度遮罩，同时对边缘去除关键色溢出
+    ///
+    /// 距离按 0.0 ~ 1.0 归一化，4:2:0 下每 2x2 像素共用一个透明度
+    pub fn chroma_key(
+        &mut self,
+        (key_u, key_v): (u8, u8),
+        similarity: f32,
+        smoothness: f32,
+        spill: f32,
+    ) -> AlphaMask {
+        let (chroma_width, chroma_height) = chroma_size(self.width, self.height);
+        // 与 OBS 一致，在全范围 CbCr 上计算距离，中性色与绿幕的距离约 0.53
+        let (key_u, key_v) = (full_range_chroma(key_u), full_range_chroma(key_v));
+        let mut chroma_alpha = vec![0u8; chroma_width * chroma_height];
+        for (i, alpha) in chroma_alpha.iter_mut().enumerate() {
+            let (u, v) = (full_range_chroma(self.u[i]), full_range_chroma(self.v[i]));
+            let distance = ((u - key_u).powi(2) + (v - key_v).powi(2)).sqrt();
+            let base = distance - similarity;
+            let full = (base / smoothness.max(f32::EPSILON))
+                .clamp(0.0, 1.0)
+                .powf(1.5);
+            let spill = (base / spill.max(f32::EPSILON)).clamp(0.0, 1.0).powf(1.5);
+            // 溢色去除：色度向中性灰收缩
+            self.u[i] = (128.0 + (self.u[i] as f32 - 128.0) * spill).round() as u8;
+            self.v[i] = (128.0 + (self.v[i] as f32 - 128.0) * spill).round() as u8;
+            *alpha = (full * 255.0).round() as u8;
+        }
+        AlphaMask::from_fn(self.width, self.height, |x, y| {
+            chroma_alpha[(y / 2) * chroma_width + x / 2]
+        })
+    }
+
+    /// 调整亮度、对比度及 gamma（亮度平面）和饱和度（色度平面），均通过查找表完成
+    pub fn adjust_color(&mut self, adjustment: &ColorAdjustment) {
+        let gamma = 1.0 / adjustment.gamma.max(0.01);
+        let luma: Vec<u8> = (0..=255u8)
+            .map(|y| {
+                // 有限范围 16 ~ 235 归一化后调整
+                let n = ((y as f32 - 16.0) / 219.0).clamp(0.0, 1.0).powf(gamma);
+                let n = (n - 0.5) * adjustment.contrast + 0.5 + adjustment.brightness;
+                (n * 219.0 + 16.0).round().clamp(16.0, 235.0) as u8
+            })
+            .collect();
+        let saturation = adjustment.saturation.max(0.0);
+        let chroma: Vec<u8> = (0..=255u8)
+            .map(|c| {
+                (128.0 + (c as f32 - 128.0) * saturation)
+                    .round()
+                    .clamp(16.0, 240.0) as u8
+            })
+            .collect();
+        self.y.iter_mut().for_each(|y| *y = luma[*y as usize]);
+        for c in self.u.iter_mut().chain(self.v.iter_mut()) {
+            *c = chroma[*c as usize];
+        }
+    }
+
+    /// 顺时针旋转，90 及 270 度时宽高互换
+    pub fn rotated(&self, rotation: Rotation) -> YuvFrame {
+        let quarters = u32::from(rotation) / 90;
+        if quarters == 0 {
+            return self.clone();
+        }
+        let (chroma_width, chroma_height) = chroma_size(self.width, self.height);
+        let (width, height) = if quarters % 2 == 1 {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        YuvFrame {
+            width,
+            height,
+            y: rotate_plane(&self.y, self.width, self.height, quarters),
+            u: rotate_plane(&self.u, chroma_width, chroma_height, quarters),
+            v: rotate_plane(&self.v, chroma_width, chroma_height, quarters),
+        }
+    }
+
+    /// 水平镜像及垂直翻转
+    pub fn flip(&mut self, horizontal: bool, vertical: bool) {
+        let (chroma_width, _) = chroma_size(self.width, self.height);
+        for (plane, width) in [
+            (&mut self.y, self.width),
+            (&mut self.u, chroma_width),
+            (&mut self.v, chroma_width),
+        ] {
+            if horizontal {
+                plane.chunks_mut(width).for_each(|row| row.reverse());
+            }
+            if vertical {
+                let rows: Vec<Vec<u8>> = plane.chunks(width).rev().map(<[u8]>::to_vec).collect();
+                *plane = rows.concat();
+            }
+        }
+    }
+
+    /// 按遮罩透明度将纯色混合到 (x, y) 处，超出本帧的部分被裁掉
+    pub fn fill_masked(
+        &mut self,
+        mask: &AlphaMask,
+        x: isize,
+        y: isize,
+        (cy, cu, cv): (u8, u8, u8),
+    ) {
+        for my in 0..mask.height {
+            let py = y + my as isize;
+            if py < 0 || py as usize >= self.height {
+                continue;
+            }
+            for mx in 0..mask.width {
+                let px = x + mx as isize;
+                if px < 0 || px as usize >= self.width {
+                    continue;
+                }
+                let index = py as usize * self.width + px as usize;
+                self.y[index] = blend(self.y[index], cy, mask.alpha(mx, my));
+            }
+        }
+
+        // 色度按覆盖的 2x2 像素的平均透明度混合
+        let (chroma_width, chroma_height) = chroma_size(self.width, self.height);
+        let chroma = Rect::new(x, y, mask.width, mask.height).chroma();
+        for cy_index in chroma.y..chroma.y + chroma.height as isize {
+            if cy_index < 0 || cy_index as usize >= chroma_height {
+                continue;
+            }
+            for cx_index in chroma.x..chroma.x + chroma.width as isize {
+                if cx_index < 0 || cx_index as usize >= chroma_width {
+                    continue;
+                }
+                let alpha = mask.chroma_alpha(x, y, cx_index, cy_index);
+                let index = cy_index as usize * chroma_width + cx_index as usize;
+                self.u[index] = blend(self.u[index], cu, alpha);
+                self.v[index] = blend(self.v[index], cv, alpha);
+            }
+        }
+    }
+}
+
+/// 8 位透明度遮罩，0 为全透明，255 为不透明
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlphaMask {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl AlphaMask {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width * height],
+        }
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// 遮罩外的位置视为全透明
+    pub fn alpha(&self, x: usize, y: usize) -> u8 {
+        if x < self.width && y < self.height {
+            self.data[y * self.width + x]
+        } else {
+            0
+        }
+    }
+
+    /// 由每个像素的透明度函数生成
+    pub fn from_fn(width: usize, height: usize, f: impl Fn(usize, usize) -> u8) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(x, y));
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// 遮罩左上角位于 (x, y) 时，画面 (px, py) 处的透明度
+    fn alpha_at(&self, x: isize, y: isize, px: isize, py: isize) -> u8 {
+        let (mx, my) = (px - x, py - y);
+        if mx < 0 || my < 0 {
+            0
+        } else {
+            self.alpha(mx as usize, my as usize)
+        }
+    }
+
+    /// 遮罩左上角位于 (x, y) 时，色度平面 (cx, cy) 处所覆盖的 2x2 像素的平均透明度
+    fn chroma_alpha(&self, x: isize, y: isize, cx: isize, cy: isize) -> u8 {
+        let mut alpha = 0u32;
+        for dy in 0..2 {
+            for dx in 0..2 {
+                alpha += self.alpha_at(x, y, cx * 2 + dx, cy * 2 + dy) as u32;
+            }
+        }
+        ((alpha + 2) / 4) as u8
+    }
+
+    /// 双线性缩放到指定尺寸
+    pub fn scaled(&self, width: usize, height: usize) -> Self {
+        self.scaled_region(Rect::new(0, 0, self.width, self.height), width, height)
+    }
+
+    /// 取 src 区域双线性缩放到指定尺寸
+    pub fn scaled_region(&self, src: Rect, width: usize, height: usize) -> Self {
+        let mut mask = Self::new(width, height);
+        scale_plane(
+            Plane::new(&self.data, self.width),
+            src,
+            PlaneMut::new(&mut mask.data, width, height),
+            Rect::new(0, 0, width, height),
+            None,
+        );
+        mask
+    }
+
+    /// 与左上角位于 (x, y) 的遮罩逐像素相乘，other 之外的位置变为全透明
+    pub fn multiply(&mut self, other: &AlphaMask, x: isize, y: isize) {
+        for my in 0..self.height {
+            for mx in 0..self.width {
+                let index = my * self.width + mx;
+                let alpha = other.alpha_at(x, y, mx as isize, my as isize) as u32;
+                self.data[index] = ((self.data[index] as u32 * alpha + 127) / 255) as u8;
+            }
+        }
+    }
+
+    /// 整体乘以不透明度
+    pub fn fade(&mut self, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        for value in &mut self.data {
+            *value = (*value as f32 * opacity).round() as u8;
+        }
+    }
+
+    /// 与已有透明度取较大值，用于叠加多个字形等
+    pub fn accumulate(&mut self, x: usize, y: usize, alpha: u8) {
+        if x < self.width && y < self.height {
+            let value = &mut self.data[y * self.width + x];
+            *value = (*value).max(alpha);
+        }
+    }
+}
+
+/// BT.601 有限范围 RGB 转 YUV
+pub fn rgb_to_yuv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    (y as u8, u as u8, v as u8)
+}
+
+/// 有限范围色度（16 ~ 240）转为以 0 为中心的全范围色度（-0.5 ~ 0.5）
+fn full_range_chroma(c: u8) -> f32 {
+    (c as f32 - 128.0) / 224.0
+}
+
+/// 按透明度混合两个采样值
+pub fn blend(background: u8, foreground: u8, alpha: u8) -> u8 {
+    let alpha = alpha as u32;
+    ((background as u32 * (255 - alpha) + foreground as u32 * alpha + 127) / 255) as u8
+}
+
+/// 像素矩形区域，坐标可以超出画面
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: isize,
+    pub y: isize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: isize, y: isize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// 对应的 4:2:0 色度平面区域
+    pub fn chroma(&self) -> Rect {
+        let x = self.x.div_euclid(2);
+        let y = self.y.div_euclid(2);
+        let right = (self.x + self.width as isize + 1).div_euclid(2);
+        let bottom = (self.y + self.height as isize + 1).div_euclid(2);
+        Rect::new(x, y, (right - x) as usize, (bottom - y) as usize)
+    }
+}
+
+impl YUVSource for YuvFrame {
+    fn width(&self) -> i32 {
+        self.width as i32
+    }
+
+    fn height(&self) -> i32 {
+        self.height as i32
+    }
+
+    fn y(&self) -> &[u8] {
+        &self.y
+    }
+
+    fn u(&self) -> &[u8] {
+        &self.u
+    }
+
+    fn v(&self) -> &[u8] {
+        &self.v
+    }
+
+    fn y_stride(&self) -> i32 {
+        self.width as i32
+    }
+
+    fn u_stride(&self) -> i32 {
+        chroma_size(self.width, self.height).0 as i32
+    }
+
+    fn v_stride(&self) -> i32 {
+        chroma_size(self.width, self.height).0 as i32
+    }
+}
+
+/// 4:2:0 色度平面尺寸，奇数尺寸向上取整
+pub fn chroma_size(width: usize, height: usize) -> (usize, usize) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+struct Plane<'a> {
+    data: &'a [u8],
+    stride: usize,
+}
+
+impl<'a> Plane<'a> {
+    fn new(data: &'a [u8], stride: usize) -> Self {
+        Self { data, stride }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.data[y * self.stride + x] as u32
+    }
+}
+
+struct PlaneMut<'a> {
+    data: &'a mut [u8],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> PlaneMut<'a> {
+    fn new(data: &'a mut [u8], width: usize, height: usize) -> Self {
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+}
+
+/// 源区域内一个采样点的两个相邻像素及第二个像素的权重（0~256）
+fn sample_positions(src_start: isize, src_len: usize, dst_len: usize) -> Vec<(usize, usize, u32)> {
+    (0..dst_len)
+        .map(|d| {
+            // 像素中心对齐，8 位定点小数
+            let pos = (((2 * d + 1) * src_len * 256) / (2 * dst_len)) as isize - 128;
+            let pos = pos.max(0) as usize;
+            let i0 = (pos >> 8).min(src_len - 1);
+            let i1 = (i0 + 1).min(src_len - 1);
+            let base = src_start.max(0) as usize;
+            (base + i0, base + i1, (pos & 0xff) as u32)
+        })
+        .collect()
+}
+
+/// alpha 给出目标平面各位置的混合透明度，为 None 时直接覆盖
+fn scale_plane(
+    src: Plane,
+    src_rect: Rect,
+    dst: PlaneMut,
+    dst_rect: Rect,
+    alpha: Option<&dyn Fn(isize, isize) -> u8>,
+) {
+    // 源区域限制在源平面内
+    let src_height = src.data.len() / src.stride.max(1);
+    let src_x = src_rect.x.clamp(0, src.stride as isize);
+    let src_y = src_rect.y.clamp(0, src_height as isize);
+    let src_width = src_rect.width.min(src.stride - src_x as usize);
+    let src_h = src_rect.height.min(src_height - src_y as usize);
+    if src_width == 0 || src_h == 0 || dst_rect.is_empty() {
+        return;
+    }
+
+    let columns = sample_positions(src_x, src_width, dst_rect.width);
+    let rows = sample_positions(src_y, src_h, dst_rect.height);
+    for (dy, &(y0, y1, wy)) in rows.iter().enumerate() {
+        let y = dst_rect.y + dy as isize;
+        if y < 0 || y as usize >= dst.height {
+            continue;
+        }
+        let row = &mut dst.data[y as usize * dst.width..(y as usize + 1) * dst.width];
+        for (dx, &(x0, x1, wx)) in columns.iter().enumerate() {
+            let x = dst_rect.x + dx as isize;
+            if x < 0 || x as usize >= dst.width {
+                continue;
+            }
+            let alpha = alpha.map(|f| f(x, y)).unwrap_or(255);
+            if alpha == 0 {
+                continue;
+            }
+            let top = src.pixel(x0, y0) * (256 - wx) + src.pixel(x1, y0) * wx;
+            let bottom = src.pixel(x0, y1) * (256 - wx) + src.pixel(x1, y1) * wx;
+            let value = ((top * (256 - wy) + bottom * wy + (1 << 15)) >> 16) as u8;
+            row[x as usize] = blend(row[x as usize], value, alpha);
+        }
+    }
+}
+
+fn box_blur_plane(
+    plane: &mut [u8],
+    width: usize,
+    height: usize,
+    radius: usize,
+    scratch: &mut Vec<u8>,
+) {
+    for y in 0..height {
+        blur_line(plane, y * width, 1, width, radius, scratch);
+    }
+    for x in 0..width {
+        blur_line(plane, x, width, height, radius, scratch);
+    }
+}
+
+/// 对从 start 开始、间隔 stride 的 len 个采样做滑动平均，边缘重复边界值
+fn blur_line(
+    plane: &mut [u8],
+    start: usize,
+    stride: usize,
+    len: usize,
+    radius: usize,
+    scratch: &mut Vec<u8>,
+) {
+    if len == 0 {
+        return;
+    }
+    scratch.clear();
+    scratch.extend((0..len).map(|i| plane[start + i * stride]));
+    let at = |i: isize| scratch[i.clamp(0, len as isize - 1) as usize] as u32;
+    let radius = radius as isize;
+    let window = (2 * radius + 1) as u32;
+    let mut sum: u32 = (-radius..=radius).map(at).sum();
+    for i in 0..len as isize {
+        plane[start + i as usize * stride] = ((sum + window / 2) / window) as u8;
+        sum = sum + at(i + radius + 1) - at(i - radius);
+    }
+}
+
+/// 将 width x height 的平面顺时针旋转 quarters 个 90 度
+fn rotate_plane(src: &[u8], width: usize, height: usize, quarters: u32) -> Vec<u8> {
+    let mut plane = vec![0; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let index = match quarters % 4 {
+                1 => x * height + (height - 1 - y),
+                2 => (height - 1 - y) * width + (width - 1 - x),
+                3 => (width - 1 - x) * height + y,
+                _ => y * width + x,
+            };
+            plane[index] = src[y * width + x];
+        }
+    }
+    plane
+}
+
+fn copy_plane(src: &[u8], stride: usize, width: usize, height: usize) -> Vec<u8> {
+    let mut plane = Vec::with_capacity(width * height);
+    for row in src.chunks(stride).take(height) {
+        plane.extend_from_slice(&row[..width.min(row.len())]);
+    }
+    plane.resize(width * height, 0);
+    plane
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blur_spreads_impulse() {
+        let mut frame = YuvFrame::new(9, 1, (0, 128, 128));
+        let mut impulse = YuvFrame::new(1, 1, (255, 128, 128));
+        frame.draw_scaled(&impulse, impulse.rect(), Rect::new(4, 0, 1, 1));
+        frame.blur(1);
+        let y = frame.y();
+        assert!(y[4] < 255 && y[3] > 0 && y[5] > 0);
+        assert_eq!(y[3], y[5]);
+
+        impulse.blur(3);
+        assert_eq!(impulse.y(), &[255]);
+    }
+
+    #[test]
+    fn rgba_chroma_ignores_transparent_pixels() {
+        // 左列为不透明的红色，右列为全透明的黑色
+        let rgba = [255, 0, 0, 255, 0, 0, 0, 0, 255, 0, 0, 255, 0, 0, 0, 0];
+        let (frame, alpha) = YuvFrame::from_rgba(2, 2, &rgba);
+        let (_, u, v) = rgb_to_yuv(255, 0, 0);
+        assert_eq!((frame.u()[0], frame.v()[0]), (u, v));
+        assert_eq!((alpha.alpha(0, 0), alpha.alpha(1, 0)), (255, 0));
+
+        let mut half = alpha.scaled(4, 2);
+        half.fade(0.5);
+        assert_eq!(half.alpha(0, 0), 128);
+        assert_eq!(half.alpha(3, 0), 0);
+    }
+
+    #[test]
+    fn adjust_color_with_lookup_tables() {
+        let mut frame = YuvFrame::new(2, 2, (126, 160, 100));
+        frame.adjust_color(&ColorAdjustment::default());
+        assert_eq!((frame.y()[0], frame.u()[0], frame.v()[0]), (126, 160, 100));
+
+        frame.adjust_color(&ColorAdjustment {
+            brightness: 0.1,
+            contrast: 2.0,
+            saturation: 0.0,
+            gamma: 1.0,
+        });
+        // 归一化亮度 0.502 经对比度拉到 0.505，再提亮 0.1
+        assert_eq!(frame.y()[0], 148);
+        assert_eq!((frame.u()[0], frame.v()[0]), (128, 128));
+
+        let mut dark = YuvFrame::new(2, 2, (71, 128, 128));
+        dark.adjust_color(&ColorAdjustment {
+            gamma: 2.0,
+            ..Default::default()
+        });
+        assert_eq!(dark.y()[0], 126);
+    }
+
+    #[test]
+    fn rotate_and_flip_planes() {
+        // 4x2 帧，亮度为 0 ~ 7，两块色度分别为 10 和 20
+        let mut frame = YuvFrame::new(4, 2, (0, 0, 128));
+        frame
+            .y
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, y)| *y = i as u8);
+        frame.u = vec![10, 20];
+
+        let rotated = frame.rotated(Rotation::Clockwise90);
+        assert_eq!(rotated.dimensions(), (2, 4));
+        assert_eq!(rotated.y(), &[4, 0, 5, 1, 6, 2, 7, 3]);
+        assert_eq!(rotated.u(), &[10, 20]);
+        assert_eq!(frame.rotated(Rotation::Clockwise270).u(), &[20, 10]);
+        assert_eq!(
+            frame.rotated(Rotation::Clockwise180).y(),
+            &[7, 6, 5, 4, 3, 2, 1, 0]
+        );
+
+        frame.flip(true, false);
+        assert_eq!(frame.y(), &[3, 2, 1, 0, 7, 6, 5, 4]);
+        assert_eq!(frame.u(), &[20, 10]);
+        frame.flip(false, true);
+        assert_eq!(frame.y(), &[7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+}